@@ -0,0 +1,146 @@
+//! # Cloudsync Derive
+//! Derive macros for the `cloudsync` crate, re-exported from it behind the `derive` feature
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, Index, LitStr, Path, Type};
+
+/// Derive `Unique` by cloning the one field marked `#[uuid]`
+/// The uuid type is inferred from that field's type
+///
+/// ```ignore
+/// #[derive(Unique)]
+/// struct User {
+///     #[uuid]
+///     email: String,
+///     name: String,
+/// }
+/// ```
+#[proc_macro_derive(Unique, attributes(uuid))]
+pub fn derive_unique(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_unique(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_unique(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let (member, ty) = uuid_field(&input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::cloudsync::Unique<#ty> for #name #ty_generics #where_clause {
+            fn uuid(&self) -> #ty {
+                ::std::clone::Clone::clone(&self.#member)
+            }
+        }
+    })
+}
+
+/// Find the one field marked `#[uuid]`, returning how to access it and its type
+fn uuid_field(input: &DeriveInput) -> Result<(proc_macro2::TokenStream, Type), Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new_spanned(&input.ident, "Unique can only be derived for structs")),
+    };
+    let fields: Vec<_> = match fields {
+        Fields::Named(named) => named.named.iter().collect(),
+        Fields::Unnamed(unnamed) => unnamed.unnamed.iter().collect(),
+        Fields::Unit => vec![],
+    };
+
+    let mut marked = fields.iter().enumerate().filter(|(_, field)| field.attrs.iter().any(|attr| attr.path().is_ident("uuid")));
+    let (index, field) = match (marked.next(), marked.next()) {
+        (Some(found), None) => found,
+        (None, _) => return Err(Error::new_spanned(&input.ident, "Unique needs exactly one field marked #[uuid], found none")),
+        (Some(_), Some((_, second))) => return Err(Error::new_spanned(second, "Unique needs exactly one field marked #[uuid], found more than one")),
+    };
+
+    let member = match &field.ident {
+        Some(ident) => quote!(#ident),
+        None => {
+            let index = Index::from(index);
+            quote!(#index)
+        }
+    };
+    Ok((member, field.ty.clone()))
+}
+
+/// Derive `CloudSync` with a `config()` built from the `#[cloudsync(...)]` attribute
+///
+/// - collection: the collection objects are saved to (required)
+/// - project_id, cred_path: the firebase project and credentials file, taken at runtime from `set_global_config()` when left out,
+///   or without one from the same environment variables as `CLConfig::from_env()` (falling back to application default credentials)
+/// - parent_path: the document the collection is nested under
+/// - uuid: the uuid type, only needed when no field is marked `#[uuid]`
+/// - backend: the `Backend` to store objects in, firestore when left out
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, Unique, CloudSync)]
+/// #[cloudsync(collection = "users", project_id = "my-project", cred_path = "./firebase.json")]
+/// struct User {
+///     #[uuid]
+///     email: String,
+///     name: String,
+/// }
+/// ```
+#[proc_macro_derive(CloudSync, attributes(cloudsync, uuid))]
+pub fn derive_cloudsync(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_cloudsync(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_cloudsync(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let mut collection: Option<LitStr> = None;
+    let mut project_id: Option<LitStr> = None;
+    let mut cred_path: Option<LitStr> = None;
+    let mut parent_path: Option<LitStr> = None;
+    let mut uuid: Option<Type> = None;
+    let mut backend: Option<Path> = None;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("cloudsync")) {
+        attr.parse_nested_meta(|meta| {
+            let value = meta.value()?;
+            if meta.path.is_ident("collection") {
+                collection = Some(value.parse()?);
+            } else if meta.path.is_ident("project_id") {
+                project_id = Some(value.parse()?);
+            } else if meta.path.is_ident("cred_path") {
+                cred_path = Some(value.parse()?);
+            } else if meta.path.is_ident("parent_path") {
+                parent_path = Some(value.parse()?);
+            } else if meta.path.is_ident("uuid") {
+                uuid = Some(value.parse::<LitStr>()?.parse()?);
+            } else if meta.path.is_ident("backend") {
+                backend = Some(value.parse::<LitStr>()?.parse()?);
+            } else {
+                return Err(meta.error("unknown cloudsync attribute, expected one of collection, project_id, cred_path, parent_path, uuid or backend"));
+            }
+            Ok(())
+        })?;
+    }
+
+    let collection = collection.ok_or_else(|| {
+        Error::new_spanned(&input.ident, "CloudSync needs the collection to save to, e.g. #[cloudsync(collection = \"users\")]")
+    })?;
+    let uuid = match uuid {
+        Some(uuid) => uuid,
+        None => uuid_field(&input)
+            .map_err(|_| Error::new_spanned(&input.ident, "CloudSync needs a field marked #[uuid], or the uuid type as #[cloudsync(uuid = \"...\")]"))?
+            .1,
+    };
+    let backend = backend.map(|backend| quote!(, #backend));
+    let option = |value: Option<LitStr>| match value {
+        Some(value) => quote!(::std::option::Option::Some(#value)),
+        None => quote!(::std::option::Option::None),
+    };
+    let (project_id, cred_path, parent_path) = (option(project_id), option(cred_path), option(parent_path));
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::cloudsync::CloudSync<#uuid #backend> for #name #ty_generics #where_clause {
+            fn config() -> ::cloudsync::CLConfig {
+                ::cloudsync::CLConfig::__derived(#collection, #project_id, #cred_path, #parent_path)
+            }
+        }
+    })
+}
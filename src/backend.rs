@@ -0,0 +1,410 @@
+use crate::retry::is_transient;
+use crate::{CLConfig, CloudSyncError, CredentialSource, RetryPolicy};
+use async_trait::async_trait;
+use firestore::errors::{FirestoreDatabaseError, FirestoreError, FirestoreErrorPublicGenericDetails, FirestoreSystemError};
+use firestore::FirestoreResult;
+use firestore::{FirestoreAggregatedQuerySupport, FirestoreQuerySupport};
+use firestore::{FirestoreConsistencySelector, FirestoreDb, FirestoreDbOptions, FirestoreQueryParams};
+use firestore::{FirestoreAggregatedQueryParams, FirestoreAggregation, FirestoreAggregationOperator, FirestoreAggregationOperatorCount};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use gcloud_sdk::google::firestore::v1::{batch_get_documents_response, listen_request, listen_response, target, BatchGetDocumentsRequest, BatchGetDocumentsResponse};
+use gcloud_sdk::google::firestore::v1::{CommitRequest, Document, GetDocumentRequest, ListCollectionIdsRequest, ListenRequest, ListenResponse, StructuredQuery, Target, Write};
+use chrono::Utc;
+use gcloud_sdk::{ExternalJwtFunctionSource, Token, TokenSourceType};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::OnceCell;
+
+/// The storage operations `CloudSync` is built on
+/// Documents, writes and queries use firestore's own types, so a backend sees exactly what would be sent to firestore
+/// Retrying is handled by the caller according to the config's `RetryPolicy`, so a backend should make a single attempt per call
+#[async_trait]
+pub trait Backend: Clone + Send + Sync + Sized + 'static {
+
+    /// Get a handle to the store described by this config
+    /// This is called by every operation, so it should be cheap after the first call (e.g. by caching clients)
+    async fn connect(cfg: &CLConfig) -> Result<Self, CloudSyncError>;
+
+    /// The path every document name starts with, e.g. `projects/{project_id}/databases/(default)/documents`
+    fn documents_path(&self) -> &str;
+
+    /// Atomically apply a group of writes, honouring their preconditions, field masks and transforms
+    /// A failed precondition should be reported the way firestore does: `DataNotFoundError` for a missing document
+    /// and `DataConflictError` or a `FailedPrecondition` database error for anything else
+    async fn commit(&self, writes: Vec<Write>) -> Result<(), CloudSyncError>;
+
+    /// Get the document with this id in the collection under `parent`, or `None` if it doesn't exist
+    /// `parent` is a full path, either `documents_path()` itself or a document below it
+    async fn get_doc(&self, parent: &str, collection: &str, id: &str) -> Result<Option<Document>, CloudSyncError>;
+
+    /// Get the documents with these ids that exist, in any order
+    async fn get_docs(&self, parent: &str, collection: &str, ids: &[String]) -> Result<Vec<Document>, CloudSyncError>;
+
+    /// Run a query and return every matching document
+    async fn query(&self, params: FirestoreQueryParams) -> Result<Vec<Document>, CloudSyncError>;
+
+    /// Run a query and stream the matching documents back one at a time
+    async fn query_stream(&self, params: FirestoreQueryParams) -> Result<BoxStream<'static, Result<Document, CloudSyncError>>, CloudSyncError>;
+
+    /// Count the documents a query matches
+    async fn count(&self, params: FirestoreQueryParams) -> Result<usize, CloudSyncError>;
+
+    /// Listen for changes to the documents a query matches, starting with every document it currently matches
+    /// The backend is responsible for reconnecting after transient disconnects, using `retry` to pace its attempts,
+    /// and should only end the stream (after an `Err` item) once it gives up
+    async fn listen(&self, params: FirestoreQueryParams, retry: RetryPolicy) -> Result<BoxStream<'static, Result<DocChange, CloudSyncError>>, CloudSyncError>;
+
+    /// List the ids of the collections directly under `parent`, which is `documents_path()` itself or a document below it
+    /// Backends that can't enumerate collections keep this default, which fails with `Unsupported`
+    async fn list_collection_ids(&self, _parent: &str) -> Result<Vec<String>, CloudSyncError> {
+        Err(CloudSyncError::Unsupported("this backend can't list collections".to_string()))
+    }
+}
+
+/// A change to one document seen by a listener
+///
+/// # Variants:
+/// - Changed: the document was created or updated (or newly matches the query), with its new contents
+/// - Removed: the document with this full name was deleted or no longer matches the query
+#[derive(Clone, Debug, PartialEq)]
+pub enum DocChange {
+    Changed(Document),
+    Removed(String),
+}
+
+/// The default backend, which talks to a real firestore database
+#[derive(Clone)]
+pub struct FirestoreBackend {
+    db: FirestoreDb,
+    database_path: String,
+    documents_path: String,
+}
+
+impl FirestoreBackend {
+    /// The underlying firestore client
+    pub fn db(&self) -> &FirestoreDb {
+        &self.db
+    }
+
+    /// Run operations against `db`, a client the caller built themselves, instead of one built from the config
+    /// This is an escape hatch for anything `CLConfig` can't express, like custom interceptors, auth or gRPC channel settings
+    /// It applies to every config sharing `cfg`'s project_id, database_id, credentials and scopes, replacing any client
+    /// already connected for them, and skips the checks cloudsync makes when connecting its own client
+    pub fn with_db(cfg: &CLConfig, db: FirestoreDb) {
+        let mut cache = DB_CACHE.get_or_init(Default::default).lock().unwrap();
+        cache.insert(db_key(cfg), Arc::new(OnceCell::new_with(Some(db))));
+    }
+}
+
+#[async_trait]
+impl Backend for FirestoreBackend {
+    async fn connect(cfg: &CLConfig) -> Result<Self, CloudSyncError> {
+        let database_path = format!("projects/{}/databases/{}", cfg.project_id, cfg.database_id.as_deref().unwrap_or(DEFAULT_DATABASE));
+        let documents_path = format!("{}/documents", database_path);
+        let mut db = get_fs_db(cfg).await?;
+        if let Some(read_time) = cfg.read_time {
+            db = db.clone_with_consistency_selector(FirestoreConsistencySelector::ReadTime(read_time));
+        }
+        Ok(FirestoreBackend { db, database_path, documents_path })
+    }
+
+    fn documents_path(&self) -> &str {
+        &self.documents_path
+    }
+
+    async fn commit(&self, writes: Vec<Write>) -> Result<(), CloudSyncError> {
+        let request = CommitRequest {
+            database: self.database_path.clone(),
+            writes,
+            transaction: vec![],
+        };
+        self.db.client().get().commit(request).await.map_err(FirestoreError::from)?;
+        Ok(())
+    }
+
+    async fn get_doc(&self, parent: &str, collection: &str, id: &str) -> Result<Option<Document>, CloudSyncError> {
+        let request = GetDocumentRequest {
+            name: format!("{}/{}/{}", parent, collection, id),
+            mask: None,
+            consistency_selector: self.db.get_session_params().consistency_selector.as_ref().map(TryInto::try_into).transpose()?,
+        };
+        match self.db.client().get().get_document(request).await.map_err(FirestoreError::from) {
+            Ok(response) => Ok(Some(response.into_inner())),
+            Err(FirestoreError::DataNotFoundError(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn get_docs(&self, parent: &str, collection: &str, ids: &[String]) -> Result<Vec<Document>, CloudSyncError> {
+        let request = BatchGetDocumentsRequest {
+            database: self.database_path.clone(),
+            documents: ids.iter().map(|id| format!("{}/{}/{}", parent, collection, id)).collect(),
+            mask: None,
+            consistency_selector: self.db.get_session_params().consistency_selector.as_ref().map(TryInto::try_into).transpose()?,
+        };
+        let responses = self.db.client().get().batch_get_documents(request).await.map_err(FirestoreError::from)?.into_inner();
+        let responses: Vec<BatchGetDocumentsResponse> = responses.try_collect().await.map_err(FirestoreError::from)?;
+        Ok(responses.into_iter()
+            .filter_map(|response| match response.result {
+                Some(batch_get_documents_response::Result::Found(doc)) => Some(doc),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn query(&self, params: FirestoreQueryParams) -> Result<Vec<Document>, CloudSyncError> {
+        crate::trace::log_query(&params);
+        Ok(self.db.query_doc(params).await?)
+    }
+
+    async fn query_stream(&self, params: FirestoreQueryParams) -> Result<BoxStream<'static, Result<Document, CloudSyncError>>, CloudSyncError> {
+        crate::trace::log_query(&params);
+        let docs = self.db.stream_query_doc_with_errors(params).await?;
+        Ok(docs.map_err(CloudSyncError::from).boxed())
+    }
+
+    async fn count(&self, params: FirestoreQueryParams) -> Result<usize, CloudSyncError> {
+        crate::trace::log_query(&params);
+        #[derive(Deserialize)]
+        struct CountResult {
+            count: usize,
+        }
+        let params = FirestoreAggregatedQueryParams::new(
+            params,
+            vec![FirestoreAggregation::new("count".to_string())
+                .with_operator(FirestoreAggregationOperator::Count(FirestoreAggregationOperatorCount::new()))],
+        );
+        let results: Vec<CountResult> = self.db.aggregated_query_obj(params).await?;
+        Ok(results.first().map_or(0, |result| result.count))
+    }
+
+    async fn listen(&self, params: FirestoreQueryParams, retry: RetryPolicy) -> Result<BoxStream<'static, Result<DocChange, CloudSyncError>>, CloudSyncError> {
+        crate::trace::log_query(&params);
+        let responses = listen_doc_changes(&self.db, &self.database_path, &params, None).await?;
+        let listener = Listener {
+            db: self.db.clone(),
+            database_path: self.database_path.clone(),
+            params,
+            retry,
+            responses: Some(responses),
+            resume_token: None,
+            failures: 0,
+        };
+        Ok(stream::unfold(Some(listener), |listener| async move {
+            let mut listener = listener?;
+            loop {
+                let responses = match listener.responses.as_mut() {
+                    Some(responses) => responses,
+                    None => {
+                        match listen_doc_changes(&listener.db, &listener.database_path, &listener.params, listener.resume_token.clone()).await {
+                            Ok(responses) => listener.responses = Some(responses),
+                            Err(err) => {
+                                let err = CloudSyncError::from(err);
+                                if !listener.wait_to_reconnect(is_transient(&err)).await {
+                                    return Some((Err(err), None));
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                };
+                match responses.next().await {
+                    Some(Ok(response)) => {
+                        listener.failures = 0;
+                        match listener.change(response) {
+                            Ok(Some(change)) => return Some((Ok(change), Some(listener))),
+                            Ok(None) => {}
+                            Err(err) => return Some((Err(err), None)),
+                        }
+                    }
+                    Some(Err(err)) => {
+                        listener.responses = None;
+                        let err = CloudSyncError::from(err);
+                        if !listener.wait_to_reconnect(is_transient(&err)).await {
+                            return Some((Err(err), None));
+                        }
+                    }
+                    // The server closed the stream, which it does from time to time, so pick up again from the last resume token
+                    None => {
+                        listener.responses = None;
+                        if !listener.wait_to_reconnect(true).await {
+                            return Some((Err(listen_error("Unavailable".to_string(), "the listener kept disconnecting".to_string(), true)), None));
+                        }
+                    }
+                }
+            }
+        }).boxed())
+    }
+
+    async fn list_collection_ids(&self, parent: &str) -> Result<Vec<String>, CloudSyncError> {
+        let mut ids = vec![];
+        let mut page_token = String::new();
+        loop {
+            let request = ListCollectionIdsRequest {
+                parent: parent.to_string(),
+                page_size: 0,
+                page_token,
+                consistency_selector: None,
+            };
+            let response = self.db.client().get().list_collection_ids(request).await.map_err(FirestoreError::from)?.into_inner();
+            ids.extend(response.collection_ids);
+            if response.next_page_token.is_empty() {
+                return Ok(ids);
+            }
+            page_token = response.next_page_token;
+        }
+    }
+}
+
+/// An error ending a listener, in the same shape firestore's own errors have
+fn listen_error(code: String, details: String, retry_possible: bool) -> CloudSyncError {
+    FirestoreError::DatabaseError(FirestoreDatabaseError::new(FirestoreErrorPublicGenericDetails::new(code), details, retry_possible)).into()
+}
+
+/// The id of the one query target each listener adds
+const LISTEN_TARGET_ID: i32 = 1;
+
+/// Open a listen stream on the query, picking up after `resume_token` when reconnecting
+async fn listen_doc_changes(
+    db: &FirestoreDb,
+    database_path: &str,
+    params: &FirestoreQueryParams,
+    resume_token: Option<Vec<u8>>,
+) -> FirestoreResult<BoxStream<'static, FirestoreResult<ListenResponse>>> {
+    let request = ListenRequest {
+        database: database_path.to_string(),
+        labels: HashMap::new(),
+        target_change: Some(listen_request::TargetChange::AddTarget(Target {
+            target_id: LISTEN_TARGET_ID,
+            target_type: Some(target::TargetType::Query(target::QueryTarget {
+                parent: params.parent.clone().unwrap_or_else(|| db.get_documents_path().clone()),
+                query_type: Some(target::query_target::QueryType::StructuredQuery(StructuredQuery::try_from(params.clone())?)),
+            })),
+            resume_type: resume_token.map(target::ResumeType::ResumeToken),
+            ..Default::default()
+        })),
+    };
+    let requests = stream::iter(vec![request]).chain(stream::pending());
+    let responses = db.client().get().listen(gcloud_sdk::tonic::Request::new(requests)).await?;
+    Ok(responses.into_inner().map_err(FirestoreError::from).boxed())
+}
+
+/// The state of a firestore listener between changes
+struct Listener {
+    db: FirestoreDb,
+    database_path: String,
+    params: FirestoreQueryParams,
+    retry: RetryPolicy,
+    responses: Option<BoxStream<'static, FirestoreResult<ListenResponse>>>,
+    resume_token: Option<Vec<u8>>,
+    failures: u32,
+}
+
+impl Listener {
+    /// Wait before reconnecting, or return `false` if the listener should give up instead
+    async fn wait_to_reconnect(&mut self, transient: bool) -> bool {
+        if !transient || self.failures + 1 >= self.retry.max_attempts {
+            return false;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::warn!(attempt = self.failures, "reconnecting firestore listener");
+        crate::runtime::sleep(self.retry.backoff(self.failures)).await;
+        self.failures += 1;
+        true
+    }
+
+    /// The document change carried by a listen response, if any
+    fn change(&mut self, response: ListenResponse) -> Result<Option<DocChange>, CloudSyncError> {
+        Ok(match response.response_type {
+            Some(listen_response::ResponseType::TargetChange(change)) => {
+                if let Some(cause) = change.cause {
+                    return Err(listen_error(cause.code.to_string(), cause.message, false));
+                }
+                if !change.resume_token.is_empty() {
+                    self.resume_token = Some(change.resume_token);
+                }
+                None
+            }
+            Some(listen_response::ResponseType::DocumentChange(change)) => match change.document {
+                Some(doc) if change.target_ids.contains(&LISTEN_TARGET_ID) => Some(DocChange::Changed(doc)),
+                Some(doc) => Some(DocChange::Removed(doc.name)),
+                None => None,
+            },
+            Some(listen_response::ResponseType::DocumentDelete(delete)) => Some(DocChange::Removed(delete.document)),
+            Some(listen_response::ResponseType::DocumentRemove(remove)) => Some(DocChange::Removed(remove.document)),
+            _ => None,
+        })
+    }
+}
+
+/// The id of the database every firestore project has
+const DEFAULT_DATABASE: &str = "(default)";
+
+/// The environment variable pointing firestore clients at a local emulator instead of google, e.g. `localhost:8080`
+const EMULATOR_HOST_ENV: &str = "FIRESTORE_EMULATOR_HOST";
+
+/// The emulator host from `FIRESTORE_EMULATOR_HOST`, if it's set
+fn emulator_host() -> Option<String> {
+    std::env::var(EMULATOR_HOST_ENV).ok().filter(|host| !host.is_empty())
+}
+
+/// Cache of database clients, keyed on (project_id, database_id, credentials, scopes, emulator host)
+/// Each entry is its own `OnceCell` so concurrent first calls for the same config share one connection attempt
+type DbKey = (String, Option<String>, CredentialSource, Option<Vec<String>>, Option<String>);
+type DbCache = Mutex<HashMap<DbKey, Arc<OnceCell<FirestoreDb>>>>;
+static DB_CACHE: OnceLock<DbCache> = OnceLock::new();
+
+fn db_key(cfg: &CLConfig) -> DbKey {
+    (cfg.project_id.clone(), cfg.database_id.clone(), cfg.credentials.clone(), cfg.scopes.clone(), emulator_host())
+}
+
+/// Drop every cached firestore client, e.g. before the tokio runtime they were connected on shuts down
+/// The next operation connects again lazily, so this is safe to call at any time; a test can call it at its end so the next test,
+/// running on a new runtime, doesn't reuse a client tied to the old one. `Collection` handles keep the client they already hold
+/// until they're dropped. Every write is committed before its method returns, so there's nothing buffered to flush
+pub fn shutdown() {
+    if let Some(cache) = DB_CACHE.get() {
+        cache.lock().unwrap().clear();
+    }
+}
+
+/// Get the correct FireStore database object with the specified configs and credentials
+/// The client is built once per config and reused by every later call
+async fn get_fs_db(cfg: &CLConfig) -> Result<FirestoreDb, CloudSyncError> {
+    let cell = {
+        let mut cache = DB_CACHE.get_or_init(Default::default).lock().unwrap();
+        cache.entry(db_key(cfg)).or_default().clone()
+    };
+    let db = cell.get_or_try_init(|| connect_fs_db(cfg)).await?;
+    Ok(db.clone())
+}
+
+/// Build a new FireStore database client from a config
+async fn connect_fs_db(cfg: &CLConfig) -> Result<FirestoreDb, CloudSyncError> {
+    #[cfg(feature = "tracing")]
+    tracing::info!(project_id = %cfg.project_id, "connecting to firestore");
+    let token_source = match (emulator_host(), &cfg.credentials) {
+        // The emulator doesn't check tokens, so there are no credentials to read; the client finds the host in the env itself
+        (Some(_), _) => TokenSourceType::ExternalSource(Box::new(ExternalJwtFunctionSource::new(|| async {
+            Ok(Token::new("Bearer".to_string(), "owner".into(), Utc::now() + chrono::Duration::hours(1)))
+        }))),
+        // The sdk would read the file synchronously on the executor, so it's read here and handed over as json instead
+        (None, CredentialSource::File(path)) => match crate::runtime::read_to_string(path.clone()).await {
+            Ok(json) => TokenSourceType::Json(json),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(CloudSyncError::CredentialsNotFound(path.clone())),
+            Err(err) => return Err(CloudSyncError::Connection(FirestoreError::SystemError(FirestoreSystemError::new(
+                FirestoreErrorPublicGenericDetails::new("CredentialsUnreadable".to_string()),
+                format!("couldn't read credentials file {}: {}", path.display(), err),
+            )))),
+        },
+        (None, CredentialSource::Json(json)) => TokenSourceType::Json(json.clone()),
+        (None, CredentialSource::Adc) => TokenSourceType::Default,
+    };
+    FirestoreDb::with_options_token_source(
+        FirestoreDbOptions::new(cfg.project_id.clone())
+            .with_database_id(cfg.database_id.clone().unwrap_or_else(|| DEFAULT_DATABASE.to_string())),
+        cfg.scopes.clone().unwrap_or_else(|| gcloud_sdk::GCP_DEFAULT_SCOPES.clone()),
+        token_source,
+    ).await.map_err(CloudSyncError::Connection)
+}
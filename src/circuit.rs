@@ -0,0 +1,107 @@
+use crate::retry::is_transient;
+use crate::{CLConfig, CloudSyncError};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// When calls to firestore are short-circuited instead of sent
+///
+/// # Fields:
+/// - failure_threshold: how many transient failures in a row (counting every retry attempt) open the circuit
+/// - cooldown: how long an open circuit fails every call with `CircuitOpen` before letting a single trial call through
+///
+/// While the trial call runs (the circuit is half-open) other calls still fail with `CircuitOpen`.
+/// A successful trial closes the circuit again, a failed one reopens it for another cooldown.
+/// Only transient errors count as failures: a `NotFound` or `Conflict` still means firestore answered
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerPolicy {
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerPolicy {
+    fn default() -> Self {
+        CircuitBreakerPolicy {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    opened_at: Option<Instant>,
+    trial_running: bool,
+}
+
+/// One breaker per database, since that's what goes down, shared by every collection and config pointing at it
+type BreakerKey = (String, Option<String>);
+static BREAKERS: OnceLock<Mutex<HashMap<BreakerKey, Breaker>>> = OnceLock::new();
+
+fn with_breaker<R>(cfg: &CLConfig, f: impl FnOnce(&mut Breaker) -> R) -> R {
+    with_breaker_key((cfg.project_id.clone(), cfg.database_id.clone()), f)
+}
+
+fn with_breaker_key<R>(key: BreakerKey, f: impl FnOnce(&mut Breaker) -> R) -> R {
+    let mut breakers = BREAKERS.get_or_init(Default::default).lock().unwrap();
+    f(breakers.entry(key).or_default())
+}
+
+/// Leave to make one call, handed out by `acquire()` and given back with its outcome to `record()`
+/// Only the permit of the trial call ends the half-open state, so another call finishing first can't let a second trial through.
+/// A trial permit dropped without being recorded (e.g. when the call timed out) gives the trial up for the next caller
+pub(crate) struct Permit {
+    trial: Option<BreakerKey>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(key) = self.trial.take() {
+            with_breaker_key(key, |breaker| breaker.trial_running = false);
+        }
+    }
+}
+
+/// Check whether a call may go out, failing with `CircuitOpen` if the config's breaker is open
+/// Once the cooldown has passed, the first caller through becomes the trial call
+pub(crate) fn acquire(cfg: &CLConfig) -> Result<Permit, CloudSyncError> {
+    let Some(policy) = &cfg.circuit_breaker else { return Ok(Permit { trial: None }) };
+    with_breaker(cfg, |breaker| match breaker.opened_at {
+        None => Ok(Permit { trial: None }),
+        Some(opened_at) if !breaker.trial_running && opened_at.elapsed() >= policy.cooldown => {
+            breaker.trial_running = true;
+            Ok(Permit { trial: Some((cfg.project_id.clone(), cfg.database_id.clone())) })
+        }
+        Some(_) => Err(CloudSyncError::CircuitOpen),
+    })
+}
+
+/// Count the outcome of a call that `acquire()` let through with `permit`
+pub(crate) fn record<R>(cfg: &CLConfig, mut permit: Permit, result: &Result<R, CloudSyncError>) {
+    let Some(policy) = &cfg.circuit_breaker else { return };
+    let trial = permit.trial.take().is_some();
+    with_breaker(cfg, |breaker| {
+        if trial {
+            breaker.trial_running = false;
+        }
+        match result {
+            Err(err) if is_transient(err) => {
+                breaker.failures = breaker.failures.saturating_add(1);
+                if trial || breaker.failures >= policy.failure_threshold {
+                    #[cfg(feature = "tracing")]
+                    if breaker.opened_at.is_none() || trial {
+                        tracing::warn!(project = %cfg.project_id, failures = breaker.failures, "opening circuit breaker");
+                    }
+                    breaker.opened_at = Some(Instant::now());
+                }
+            }
+            // A success that started before the circuit opened says nothing about whether it has recovered, only the trial does
+            _ if trial || breaker.opened_at.is_none() => {
+                breaker.failures = 0;
+                breaker.opened_at = None;
+            }
+            _ => {}
+        }
+    })
+}
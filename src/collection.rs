@@ -0,0 +1,115 @@
+use crate::retry::with_retry;
+use crate::{commit_deletes, delete_write, deserialize_doc, doc_exists, fetch_doc, query_params, read_all, set_write, trace};
+use crate::{Backend, CLConfig, CloudSync, CloudSyncError, FirestoreBackend, ToDocId, Unique};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use tokio::sync::OnceCell;
+
+/// A handle on the collection objects of type `S` live in, to pass around as a value instead of calling static methods
+/// It holds its config and connects once, on first use, reusing the connection for every later call
+/// Get one for a type's own config with `CloudSync::collection_handle()`, or point one at any other config with `Collection::new()`,
+/// e.g. to hand a test its own collection
+///
+/// Unlike `CloudSync::save()`, the handle always writes to its own collection, ignoring any `CloudSync::collection()` override
+pub struct Collection<S, T, B = FirestoreBackend> {
+    cfg: CLConfig,
+    backend: OnceCell<B>,
+    _objects: PhantomData<fn() -> (S, T)>,
+}
+
+impl<S, T, B: Backend> Clone for Collection<S, T, B> {
+    fn clone(&self) -> Self {
+        Collection { cfg: self.cfg.clone(), backend: self.backend.clone(), _objects: PhantomData }
+    }
+}
+
+impl<S, T, B> Collection<S, T, B> where
+    S: CloudSync<T, B>,
+    for<'a> S: Deserialize<'a> + Serialize + Unique<T> + Sync + Send + 'static,
+    T: Serialize + ToDocId + std::cmp::Eq + std::hash::Hash + Send + Sync,
+    B: Backend {
+
+    /// A handle on the collection `cfg` names
+    pub fn new(cfg: CLConfig) -> Self {
+        Collection { cfg, backend: OnceCell::new(), _objects: PhantomData }
+    }
+
+    /// The config this handle reads and writes with
+    pub fn config(&self) -> &CLConfig {
+        &self.cfg
+    }
+
+    /// The connection to the backend, made on the first call
+    async fn backend(&self) -> Result<&B, CloudSyncError> {
+        self.backend.get_or_try_init(|| B::connect(&self.cfg)).await
+    }
+
+    /// Save an object to the collection, fully overwriting any existing document with the same uuid
+    pub async fn save(&self, obj: &S) -> Result<(), CloudSyncError> {
+        let cfg = &self.cfg;
+        trace::traced("save", &cfg.collection_id(), Some(&obj.doc_id()), cfg.operation_timeout, async {
+            let backend = self.backend().await?;
+            let parent = cfg.parent(backend.documents_path());
+            let write = set_write(cfg, &parent, &cfg.collection_id(), &obj.doc_id(), obj)?;
+            with_retry!(&cfg, backend.commit(vec![write.clone()]))
+        }).await
+    }
+
+    /// Get all objects in the collection, up to the config's `default_query_limit`
+    pub async fn get(&self) -> Result<Vec<S>, CloudSyncError> {
+        let cfg = &self.cfg;
+        trace::traced("get", &cfg.collection_id(), None, cfg.operation_timeout, async {
+            read_all(self.backend().await?, cfg).await
+        }).await
+    }
+
+    /// Get a single object by its uuid, or `None` if it isn't saved
+    pub async fn get_by_id(&self, id: &T) -> Result<Option<S>, CloudSyncError> {
+        let cfg = &self.cfg;
+        trace::traced("get_by_id", &cfg.collection_id(), Some(&S::doc_id_for(id)), cfg.operation_timeout, async {
+            let backend = self.backend().await?;
+            let parent = cfg.parent(backend.documents_path());
+            match fetch_doc(backend, cfg, &parent, &cfg.collection_id(), &S::doc_id_for(id)).await? {
+                Some(doc) => Ok(Some(deserialize_doc(&doc)?)),
+                None => Ok(None),
+            }
+        }).await
+    }
+
+    /// Check whether an object with this uuid is saved, without downloading any of its fields
+    pub async fn exists(&self, id: &T) -> Result<bool, CloudSyncError> {
+        let cfg = &self.cfg;
+        trace::traced("exists", &cfg.collection_id(), Some(&S::doc_id_for(id)), cfg.operation_timeout, async {
+            let backend = self.backend().await?;
+            let parent = cfg.parent(backend.documents_path());
+            doc_exists(backend, cfg, &parent, &cfg.collection_id(), &S::doc_id_for(id)).await
+        }).await
+    }
+
+    /// Count the objects in the collection without downloading any of them
+    pub async fn count(&self) -> Result<usize, CloudSyncError> {
+        let cfg = &self.cfg;
+        trace::traced("count", &cfg.collection_id(), None, cfg.operation_timeout, async {
+            let backend = self.backend().await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id());
+            with_retry!(&cfg, backend.count(params.clone()))
+        }).await
+    }
+
+    /// Remove this object from the collection
+    pub async fn rm(&self, obj: &S) -> Result<(), CloudSyncError> {
+        self.rm_by_id(&obj.uuid()).await
+    }
+
+    /// Remove the object with this uuid from the collection, succeeding without doing anything if it isn't saved
+    pub async fn rm_by_id(&self, id: &T) -> Result<(), CloudSyncError> {
+        let cfg = &self.cfg;
+        trace::traced("rm_by_id", &cfg.collection_id(), Some(&S::doc_id_for(id)), cfg.operation_timeout, async {
+            let backend = self.backend().await?;
+            let parent = cfg.parent(backend.documents_path());
+            let delete = delete_write(&parent, &cfg.collection_id(), &S::doc_id_for(id))?;
+            commit_deletes(backend, cfg, vec![delete]).await
+        }).await
+    }
+}
@@ -0,0 +1,41 @@
+use gcloud_sdk::google::firestore::v1::{value, Document, Value};
+use std::collections::HashMap;
+
+/// The most bytes firestore stores in a single document
+pub(crate) const MAX_DOCUMENT_BYTES: usize = 1_048_576;
+
+/// The size firestore counts a document as, following its storage size rules:
+/// the document name, plus every field name and value, plus 32 bytes of overhead
+pub(crate) fn doc_size(doc: &Document) -> usize {
+    name_size(&doc.name) + fields_size(&doc.fields) + 32
+}
+
+/// A document name counts each collection and document id in its path, plus 16 bytes
+fn name_size(name: &str) -> usize {
+    let path = name.split_once("/documents/").map_or(name, |(_, path)| path);
+    path.split('/').map(string_size).sum::<usize>() + 16
+}
+
+/// Maps count each key and value
+fn fields_size(fields: &HashMap<String, Value>) -> usize {
+    fields.iter().map(|(name, value)| string_size(name) + value_size(value)).sum()
+}
+
+/// Strings count their utf-8 bytes plus one
+fn string_size(s: &str) -> usize {
+    s.len() + 1
+}
+
+/// Numbers and timestamps count 8 bytes, arrays the sum of their values and references the size of the name they hold
+fn value_size(value: &Value) -> usize {
+    match &value.value_type {
+        None | Some(value::ValueType::NullValue(_)) | Some(value::ValueType::BooleanValue(_)) => 1,
+        Some(value::ValueType::IntegerValue(_)) | Some(value::ValueType::DoubleValue(_)) | Some(value::ValueType::TimestampValue(_)) => 8,
+        Some(value::ValueType::GeoPointValue(_)) => 16,
+        Some(value::ValueType::StringValue(s)) => string_size(s),
+        Some(value::ValueType::BytesValue(bytes)) => bytes.len(),
+        Some(value::ValueType::ReferenceValue(name)) => name_size(name),
+        Some(value::ValueType::ArrayValue(array)) => array.values.iter().map(value_size).sum(),
+        Some(value::ValueType::MapValue(map)) => fields_size(&map.fields),
+    }
+}
@@ -0,0 +1,106 @@
+use firestore::errors::FirestoreError;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Every error that can come out of a cloudsync operation
+///
+/// # Variants:
+/// - CredentialsNotFound: the credentials file in the config doesn't exist
+/// - Connection: the database client couldn't be built or reached
+/// - Serialization: an object couldn't be converted to or from a firestore document
+/// - NotFound: a document that was required to exist wasn't there
+/// - DuplicateUuid: two documents in a collection hold objects with this same uuid
+/// - Conflict: a conditional write was rejected because the document changed since it was read
+/// - InvalidDocumentId: an object's uuid maps to this document id, which firestore doesn't allow
+/// - InvalidField: a field name passed to a method isn't part of the object
+/// - WrongFieldType: a stored field doesn't hold the kind of value (`expected`) an operation needs
+/// - MissingEnvVar: a required environment variable isn't set
+/// - MissingConfig: a config was built without these required fields
+/// - GlobalConfigNotSet: `CLConfig::with_collection()` was called before `set_global_config()`
+/// - InvalidQuery: the query breaks one of firestore's rules, e.g. range filters on more than one field
+/// - MissingIndex: firestore needs a composite index for this query, which can be created at the console link it sent (if any)
+/// - DocumentTooLarge: the object with this id serializes to a document of `bytes` bytes, more than the config's `max_document_bytes`
+/// - TooManyWrites: an atomic operation was asked to write more documents than firestore allows in one commit
+/// - CircuitOpen: firestore kept failing, so the config's circuit breaker is failing calls without sending them until its cooldown passes
+/// - Timeout: the operation didn't finish within the configured `operation_timeout`
+/// - Unsupported: the operation isn't possible with the current setup
+/// - Batch: a batched operation failed partway, after `committed` objects were already written
+/// - Firestore: any other error reported by firestore
+#[derive(Debug)]
+pub enum CloudSyncError {
+    CredentialsNotFound(PathBuf),
+    Connection(FirestoreError),
+    Serialization(FirestoreError),
+    NotFound { collection: String, id: String },
+    Conflict { collection: String, id: String },
+    DuplicateUuid(String),
+    InvalidDocumentId(String),
+    InvalidField(String),
+    WrongFieldType { field: String, expected: String },
+    MissingEnvVar(String),
+    MissingConfig(Vec<String>),
+    GlobalConfigNotSet,
+    InvalidQuery(String),
+    MissingIndex(Option<String>),
+    DocumentTooLarge { id: String, bytes: usize },
+    TooManyWrites { count: usize, max: usize },
+    CircuitOpen,
+    Timeout(Duration),
+    Unsupported(String),
+    Batch { committed: usize, source: Box<CloudSyncError> },
+    Firestore(FirestoreError),
+}
+
+impl fmt::Display for CloudSyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CloudSyncError::CredentialsNotFound(path) => write!(f, "credentials file not found: {}", path.display()),
+            CloudSyncError::Connection(err) => write!(f, "failed to connect to firestore: {}", err),
+            CloudSyncError::Serialization(err) => write!(f, "failed to (de)serialize object: {}", err),
+            CloudSyncError::NotFound { collection, id } => write!(f, "document `{}` not found in collection `{}`", id, collection),
+            CloudSyncError::Conflict { collection, id } => write!(f, "document `{}` in collection `{}` changed since it was read", id, collection),
+            CloudSyncError::DuplicateUuid(id) => write!(f, "more than one document holds the uuid `{}`", id),
+            CloudSyncError::InvalidDocumentId(id) => write!(
+                f,
+                "invalid document id `{}`: ids must be 1 to 1500 bytes, can't contain `/`, and can't be `.`, `..` or match `__.*__`",
+                id
+            ),
+            CloudSyncError::InvalidField(field) => write!(f, "field `{}` does not exist on this object", field),
+            CloudSyncError::WrongFieldType { field, expected } => write!(f, "field `{}` does not hold {}", field, expected),
+            CloudSyncError::MissingEnvVar(var) => write!(f, "environment variable not set: {}", var),
+            CloudSyncError::MissingConfig(fields) => write!(f, "config is missing required fields: {}", fields.join(", ")),
+            CloudSyncError::GlobalConfigNotSet => write!(f, "the global config isn't set, call set_global_config() first"),
+            CloudSyncError::InvalidQuery(reason) => write!(f, "invalid query: {}", reason),
+            CloudSyncError::MissingIndex(Some(url)) => write!(f, "this query needs a composite index, create it at {}", url),
+            CloudSyncError::MissingIndex(None) => write!(f, "this query needs a composite index"),
+            CloudSyncError::DocumentTooLarge { id, bytes } => write!(f, "document `{}` is too large to save ({} bytes)", id, bytes),
+            CloudSyncError::TooManyWrites { count, max } => write!(f, "{} writes can't be committed atomically, the limit is {}", count, max),
+            CloudSyncError::CircuitOpen => write!(f, "firestore keeps failing, calls are short-circuited until the circuit breaker's cooldown passes"),
+            CloudSyncError::Timeout(timeout) => write!(f, "operation timed out after {:?}", timeout),
+            CloudSyncError::Unsupported(reason) => write!(f, "unsupported: {}", reason),
+            CloudSyncError::Batch { committed, source } => write!(f, "batch failed after {} objects were committed: {}", committed, source),
+            CloudSyncError::Firestore(err) => write!(f, "firestore error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CloudSyncError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CloudSyncError::Connection(err) | CloudSyncError::Serialization(err) | CloudSyncError::Firestore(err) => Some(err),
+            CloudSyncError::Batch { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<FirestoreError> for CloudSyncError {
+    fn from(err: FirestoreError) -> Self {
+        match err {
+            FirestoreError::NetworkError(_) | FirestoreError::SystemError(_) => CloudSyncError::Connection(err),
+            FirestoreError::SerializeError(_) | FirestoreError::DeserializeError(_) => CloudSyncError::Serialization(err),
+            _ => CloudSyncError::Firestore(err),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{Backend, CloudSync, CloudSyncError, ToDocId, Unique};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Conveniences built on top of `CloudSync`, kept apart so the core trait stays small
+/// Implemented for every `CloudSync` type, so importing it is all that's needed
+#[async_trait]
+pub trait CloudSyncExt<T, B>: CloudSync<T, B> where
+    for<'a> Self: Deserialize<'a> + Serialize + Unique<T> + Sync + Send + 'static,
+    T: Serialize + ToDocId + std::cmp::Eq + std::hash::Hash + Send + Sync,
+    B: Backend {
+
+    /// Save every object from an iterator, in batches like `save_batch()`
+    async fn save_all<I>(objs: I) -> Result<(), CloudSyncError> where I: IntoIterator<Item = Self> + Send, I::IntoIter: Send {
+        let objs: Vec<Self> = objs.into_iter().collect();
+        Self::save_batch(&objs).await
+    }
+
+    /// Get every object in the collection for which `predicate` holds
+    /// The filtering happens after the whole collection is read; prefer `get_where()` and friends when firestore can filter instead
+    async fn find<P>(predicate: P) -> Result<Vec<Self>, CloudSyncError> where P: Fn(&Self) -> bool + Send {
+        Ok(Self::get().await?.into_iter().filter(|obj| predicate(obj)).collect())
+    }
+
+    /// Get the object with this uuid, or `default` if it isn't stored
+    async fn get_or_default(id: &T, default: Self) -> Result<Self, CloudSyncError> {
+        Ok(Self::get_by_id(id).await?.unwrap_or(default))
+    }
+}
+
+impl<S, T, B> CloudSyncExt<T, B> for S where
+    S: CloudSync<T, B>,
+    for<'a> S: Deserialize<'a> + Serialize + Unique<T> + Sync + Send + 'static,
+    T: Serialize + ToDocId + std::cmp::Eq + std::hash::Hash + Send + Sync,
+    B: Backend {}
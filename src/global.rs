@@ -0,0 +1,35 @@
+use crate::CredentialSource;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// The settings every type in an app usually shares, set once with `set_global_config()`
+/// so that each type's `config()` only has to name its collection with `CLConfig::with_collection()`
+///
+/// # Fields:
+/// - project_id: name of the the project in firebase
+/// - credentials: where the credentials come from (see `CredentialSource`)
+#[derive(Clone, Debug)]
+pub struct GlobalConfig {
+    pub project_id: String,
+    pub credentials: CredentialSource,
+}
+
+impl GlobalConfig {
+    /// Shared settings reading the credentials from the json file at `cred_path`
+    pub fn new(project_id: impl Into<String>, cred_path: impl Into<PathBuf>) -> Self {
+        GlobalConfig { project_id: project_id.into(), credentials: CredentialSource::File(cred_path.into()) }
+    }
+}
+
+static GLOBAL_CONFIG: OnceLock<GlobalConfig> = OnceLock::new();
+
+/// Set the settings shared by every type, typically once at startup
+/// It can only be set once; a second call hands its config back in the `Err`
+pub fn set_global_config(cfg: GlobalConfig) -> Result<(), GlobalConfig> {
+    GLOBAL_CONFIG.set(cfg)
+}
+
+/// The shared settings, or `None` if `set_global_config()` hasn't been called yet
+pub fn global_config() -> Option<&'static GlobalConfig> {
+    GLOBAL_CONFIG.get()
+}
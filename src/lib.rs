@@ -11,138 +11,4399 @@
 //! ## Usage
 //! - Make sure the object you want to extend satisfies the trait bounds (notably Serialize and Deserialize)
 //! - impl Unique and CloudSync for the object (you should just need to implement `uuid()` and `config()`)
+//! - With the `derive` feature (on by default), `#[derive(Unique)]` can implement `Unique` by marking the uuid field with `#[uuid]`
+//! - `#[derive(CloudSync)]` implements `config()` from `#[cloudsync(collection = "users", project_id = "...", cred_path = "...")]`, reading project_id and cred_path from the global config or the environment when they're left out
+//! - When many types share a project and credentials, call `set_global_config()` once at startup and have each `config()` return `CLConfig::with_collection("users")`
+//! - For multi-tenant apps, set `collection_prefix` in the config (e.g. from the current tenant) to keep each tenant's objects in collections of their own
+//! - With the `uuid` feature, `uuid::Uuid` keys get `save_with_new_uuid()`, which gives an object a fresh v4 uuid when it has none yet (implement `AssignUuid`)
+//! - Objects are stored under their uuid's string form; for a uuid with no `Display` form (like a composite key), implement `ToDocId` for it instead
+//! - To change fields only known at runtime, pass `patch()` a map of (dot-notated) field paths to `serde_json::Value`s
+//! - `MyObject::collection_handle()` gives a `Collection` to pass around as a value, with `save()`, `get()`, `get_by_id()` and `rm()` as methods;
+//!   `Collection::new(cfg)` points one at any config, which makes injecting a test collection easy
+//! - To read a collection into a different (e.g. slimmer) type than the one written to it, use `get_as::<ReadModel>()`
+//! - `copy_to("archive")` saves a copy of an object into another collection, and `move_to("archive")` moves it there in one atomic commit
+//! - `get_id_range(start, end)` reads the objects with document ids in `[start, end)`, e.g. to split a large collection into partitions
+//! - For batch jobs over big collections, `scan_parallel(shards, concurrency, f)` runs `f` over every object, reading several id ranges at once
+//! - `get_group()` reads the objects in every collection with the configured name, wherever it's nested (a collection group query)
+//! - For anything the `get_where...` helpers don't cover, build a query: `MyObject::query().filter("age", FilterOp::Gte, 18).order_by("name", Order::Asc).limit(20).fetch()`
+//! - `get_field::<V>(&id, "status")` reads a single field of one object without fetching the rest of it
+//! - For read-through caches, `get_arc()` and `hash_arc()` hand back objects already wrapped in `Arc`
+//! - Import `CloudSyncExt` for conveniences like `save_all()`, `find()` and `get_or_default()` on every CloudSync type
+//! - Objects are stored in firestore by default; to store them elsewhere, implement `Backend` and name it when implementing CloudSync (`impl CloudSync<String, MyBackend> for MyObject`)
+//! - `shutdown()` drops every cached firestore client (the next operation reconnects), e.g. before a runtime they were connected on goes away
+//! - To use a `FirestoreDb` you've built yourself (custom interceptors, auth or channel settings), hand it over with `FirestoreBackend::with_db()`
+//! - `watch()` (like `listen()`) streams every object already in the collection as `Added`, then each change as it happens, so a reactive client needs no separate initial `get()`
+//! - Enable the `tracing` feature to get a span (with the collection, document id and elapsed time) around every operation, plus events for errors, retries and new connections, and a debug-level event with every query's collection, filters, order and limit right before it runs
+//! - To feed operation counts and latencies into a metrics system, implement `Observer` and register it with `set_observer()`
+//! - Retry backoffs, listener reconnects and `operation_timeout` use tokio's timers through the default `runtime-tokio` feature; turn default features off to use portable timers under another executor (the firestore client itself still needs a tokio runtime, so this mostly matters for the in-memory backend)
+//! - Set `circuit_breaker` in the config to fail fast with `CircuitOpen` while firestore is down instead of retrying into the outage
+//! - For reports that must be internally consistent, set `read_time` in the config (or call `at_time()` on a query) to read a snapshot of the past hour
+//! - Set `dry_run` in the config to have the `rm` methods and `clear_collection()` log and count what they would delete without deleting it
+//! - For reads of thousands of documents, set `blocking_deserialize_threshold` in the config to deserialize them on a blocking thread rather than on the executor
+//! - A `ServerTimestamp` field left `Pending` is set to firestore's own time when the object is saved, avoiding clock skew between clients
+//! - For integration tests, the `test-util` feature adds `TestCollection`, a guard that points a test at its own uniquely named collection, seeds it, and clears it when dropped
 //! - If you set everything up correctly, it should work!
 
-use firestore::{FirestoreDb, FirestoreQueryParams, FirestoreDbOptions, FirestoreQueryCollection};
+// Lets the derive macros refer to `::cloudsync` from inside this crate too
+extern crate self as cloudsync;
+
+use firestore::errors::FirestoreError;
+use firestore::timestamp_utils::{from_timestamp, to_timestamp};
+use firestore::{FirestoreDb, FirestoreQueryParams, FirestoreQueryCollection};
+use firestore::{FirestoreQueryCursor, FirestoreQueryDirection, FirestoreQueryOrder, FirestoreValue};
+use firestore::{FirestoreQueryFilter, FirestoreQueryFilterCompare, FirestoreQueryFilterUnary};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, Stream, StreamExt};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use gcloud_sdk::TokenSourceType;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use gcloud_sdk::google::firestore::v1::document_transform::{field_transform, FieldTransform};
+use gcloud_sdk::google::firestore::v1::{precondition, value, write, ArrayValue, Document, DocumentMask, MapValue, Precondition, Value, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+mod error;
+pub use error::CloudSyncError;
+
+mod retry;
+use retry::with_retry;
+pub use retry::RetryPolicy;
+
+mod circuit;
+pub use circuit::CircuitBreakerPolicy;
+
+mod trace;
+mod runtime;
+
+mod ext;
+pub use ext::CloudSyncExt;
+
+mod collection;
+pub use collection::Collection;
+
+mod query;
+pub use query::Query;
+
+mod observer;
+pub use observer::{clear_observer, set_observer, Observer};
+
+mod global;
+pub use global::{global_config, set_global_config, GlobalConfig};
+
+mod doc_size;
+use doc_size::{doc_size, MAX_DOCUMENT_BYTES};
+
+mod server_time;
+use server_time::SERVER_TIMESTAMP_SENTINEL;
+pub use server_time::ServerTimestamp;
+
+mod backend;
+pub use backend::{shutdown, Backend, DocChange, FirestoreBackend};
+
+#[cfg(feature = "test-util")]
+mod test_util;
+#[cfg(feature = "test-util")]
+pub use test_util::TestCollection;
+
+mod memory;
+pub use memory::InMemoryBackend;
+
+#[cfg(feature = "derive")]
+pub use cloudsync_derive::{CloudSync, Unique};
 
-/// Internal error type
-type Error = Box<dyn std::error::Error + Send + Sync>;
+/// The special field path firestore uses to refer to a document's id in queries
+/// Pass this as the field to methods like `get_ordered()` to use the document id
+pub const DOC_ID_FIELD: &str = "__name__";
 
-/// Get the correct FireStore database object with the specified configs and credentials
-async fn get_fs_db(cfg: &CLConfig) -> Result<FirestoreDb, Error> {
-    Ok(FirestoreDb::with_options_token_source(
-        FirestoreDbOptions::new(cfg.project_id.clone(),),
-        gcloud_sdk::GCP_DEFAULT_SCOPES.clone(),
-        TokenSourceType::File(PathBuf::from(&cfg.cred_path)),
-    ).await?)
+/// The full name of the document with this id, in a collection under `parent` (see `CLConfig::parent()`)
+fn doc_path(parent: &str, collection: &str, id: &str) -> String {
+    format!("{}/{}/{}", parent, collection, id)
 }
 
+/// The id of a document, which is the last segment of its full name
+fn doc_id_of(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
 
-/// Allows a serializable object to be saved in the cloud using firestore
-#[async_trait]
-pub trait CloudSync<T> where 
-    for<'a> Self: Deserialize<'a> + Serialize + Unique<T> + Sync + Send,
-    T: Serialize + std::fmt::Display + std::cmp::Eq + std::hash::Hash + Send + Sync {
+/// A reference to the document with this id, for comparing against `DOC_ID_FIELD` in queries
+/// Fails with `InvalidDocumentId` for an id firestore wouldn't accept, since the cursors and filters using it are built from uuids
+fn doc_ref(parent: &str, collection: &str, id: &str) -> Result<FirestoreValue, CloudSyncError> {
+    check_doc_id(id)?;
+    Ok(FirestoreValue::from(Value {
+        value_type: Some(value::ValueType::ReferenceValue(doc_path(parent, collection, id))),
+    }))
+}
 
-    // Save an object to the collection specified in the config
-    async fn save(&self) -> Result<(), Error> {
-        let cfg = Self::config();
-        let db = get_fs_db(&cfg).await?;
-        db.delete_by_id(&cfg.collection, self.uuid().to_string()).await?;
-        db.create_obj(&cfg.collection, self.uuid().to_string(), self).await?;
-        Ok(())
+/// The parameters for a query over a whole collection under `parent`
+fn query_params(parent: &str, collection: &str) -> FirestoreQueryParams {
+    FirestoreQueryParams::new(FirestoreQueryCollection::Single(collection.to_string())).with_parent(parent.to_string())
+}
+
+/// The parameters for reading the config's whole collection under `parent`, capped at its `default_query_limit`
+fn read_params(cfg: &CLConfig, parent: &str) -> FirestoreQueryParams {
+    let params = query_params(parent, &cfg.collection_id());
+    match cfg.default_query_limit {
+        Some(limit) => params.with_limit(limit),
+        None => params,
     }
+}
+
+/// The characters of firestore's auto-ids, in the order firestore sorts them
+const AUTO_ID_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
-    /// Remove this object from the collection
-    async fn rm(&self) -> Result<(), Error> {
-        let cfg = Self::config();
-        let db = get_fs_db(&cfg).await?;
-        db.delete_by_id(&cfg.collection, self.uuid().to_string()).await?;
-        Ok(())
+/// The ids splitting the id space into `shards` ranges for `scan_parallel()`, spread evenly over two-character auto-id prefixes
+/// There are only so many of those, so asking for more shards than that gets that many
+fn shard_bounds(shards: usize) -> Vec<String> {
+    let base = AUTO_ID_CHARS.len();
+    let shards = shards.clamp(1, base * base);
+    (1..shards)
+        .map(|i| i * base * base / shards)
+        .map(|n| String::from_utf8(vec![AUTO_ID_CHARS[n / base], AUTO_ID_CHARS[n % base]]).unwrap())
+        .collect()
+}
+
+/// The parameters for a query over the documents with ids from `start` (included) to `end` (excluded), an empty bound being open
+fn id_range_params(parent: &str, collection: &str, start: &str, end: &str) -> Result<FirestoreQueryParams, CloudSyncError> {
+    let mut params = query_params(parent, collection)
+        .with_order_by(vec![FirestoreQueryOrder::new(DOC_ID_FIELD.to_string(), FirestoreQueryDirection::Ascending)]);
+    if !start.is_empty() {
+        params = params.with_start_at(FirestoreQueryCursor::BeforeValue(vec![doc_ref(parent, collection, start)?]));
     }
+    if !end.is_empty() {
+        params = params.with_end_at(FirestoreQueryCursor::BeforeValue(vec![doc_ref(parent, collection, end)?]));
+    }
+    Ok(params)
+}
 
-    /// Get all objects from a collection in a vector
-    /// This is the typical manner in which you would iterate over all of the objects in the same collection as this one
-    async fn get() ->  Result<Vec<Self>, Error> {
-        let cfg = Self::config();
-        let db = get_fs_db(&cfg).await?;
-        let objects: Vec<Self> = db.query_obj(FirestoreQueryParams::new(FirestoreQueryCollection::Single(cfg.collection))).await?;
-        Ok(objects)
+/// Convert a value into a firestore value that queries can compare against, or `None` if it serializes to null
+fn query_value<V: Serialize>(value: &V) -> Result<Option<FirestoreValue>, CloudSyncError> {
+    // Serializing through a document (rather than `FirestoreValue::from`) surfaces serialization errors
+    #[derive(Serialize)]
+    struct Wrapper<'a, V> {
+        value: &'a V,
     }
+    let mut doc = serialize_with_nulls("", &Wrapper { value })?;
+    Ok(doc.fields.remove("value").filter(|value| !is_null(value)).map(FirestoreValue::from))
+}
 
-    /// Get all items from the collection this object is in as a HashMap
-    /// This is the typical manner in which you would find a specific object
-    async fn hash() -> Result<HashMap<T, Self>, Error> {
-        let cfg = Self::config();
-        let db = get_fs_db(&cfg).await?;
-        let objects: Vec<Self> = db.query_obj(FirestoreQueryParams::new(FirestoreQueryCollection::Single(cfg.collection))).await?;
-        let mut hash = HashMap::new();
-        for obj in objects {
-            hash.insert(obj.uuid(), obj);
+/// Serialize an object into the document at `path`, keeping `None` fields (and array elements) as nulls,
+/// which the firestore serializer would otherwise leave out
+fn serialize_with_nulls<S: Serialize>(path: &str, obj: &S) -> Result<Document, CloudSyncError> {
+    struct WithNulls<'a, S>(&'a S);
+    impl<S: Serialize> Serialize for WithNulls<'_, S> {
+        fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+            firestore::serialize_as_null::serialize(&Some(self.0), serializer)
         }
-        Ok(hash)
     }
+    Ok(FirestoreDb::serialize_to_doc(path, &WithNulls(obj))?)
+}
 
-    // TODO
-    // async fn this()
-    
-    /// Get this objects cloud config, not intended for use outside of the crate 
-    fn config() -> CLConfig;
+/// Whether a serialized value is null, or empty as serde leaves a unit
+fn is_null(value: &Value) -> bool {
+    matches!(value.value_type, None | Some(value::ValueType::NullValue(_)))
 }
 
-/// Each object implementing this trait can provide a uuid for itself
-pub trait Unique<T> where T: Serialize {
+/// Convert a list of values into firestore values, keeping the ones that serialize to null as explicit nulls
+fn to_values<V: Serialize>(values: &[V]) -> Result<Vec<Value>, CloudSyncError> {
+    values.iter()
+        .map(|value| Ok(query_value(value)?.map_or(Value { value_type: Some(value::ValueType::NullValue(0)) }, |value| value.value)))
+        .collect()
+}
 
-    /// Get the uuid of this object
-    fn uuid(&self) -> T;
+/// Deserialize a document's fields into an object
+/// Unlike `FirestoreDb::deserialize_doc_to()` this leaves out the `_firestore_id` and other metadata fields the client adds,
+/// which would otherwise show up in `serde_json::Value` reads and trip `deny_unknown_fields`
+fn deserialize_doc<S: for<'a> Deserialize<'a>>(doc: &Document) -> Result<S, FirestoreError> {
+    S::deserialize(FirestoreValue::from(Value { value_type: Some(value::ValueType::MapValue(MapValue { fields: doc.fields.clone() })) }))
 }
 
-/// The config for how this object syncs with the cloud
-/// 
-/// # Fields:
-/// - project_id: name of the the project in firebase
-/// - cred_path: the location of the credentials json file downloaded from firebase
-/// - collection: the name of the collection that objects of this type should be saved to
-/// (note: you could write this code such that the collection changes based on paramteres in the object, this is untested)
-///
-pub struct CLConfig {
-    pub project_id: String,
-    pub cred_path: String,
-    pub collection: String,
+/// Deserialize a list of documents into objects
+fn from_docs<S: for<'a> Deserialize<'a>>(docs: &[Document]) -> Result<Vec<S>, CloudSyncError> {
+    Ok(docs.iter().map(deserialize_doc).collect::<Result<_, _>>()?)
 }
 
-// Note: This testing setup just wont work unless you set everything up in firebase the exact same
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Deserialize a list of documents into objects like `from_docs()`, but on a blocking thread once there are at least
+/// `blocking_deserialize_threshold` of them, so a big read doesn't hold up the other tasks on the executor
+async fn deserialize_docs<S>(cfg: &CLConfig, docs: Vec<Document>) -> Result<Vec<S>, CloudSyncError>
+where S: for<'a> Deserialize<'a> + Send + 'static {
+    match cfg.blocking_deserialize_threshold {
+        Some(threshold) if docs.len() >= threshold => runtime::spawn_blocking(move || from_docs(&docs)).await,
+        _ => from_docs(&docs),
+    }
+}
 
-    #[derive(Deserialize, Serialize)]
-    struct TestOBJ {
-        key: String,
-        data: String,
+/// The field `soft_rm()` stamps a document with
+const DELETED_AT_FIELD: &str = "deleted_at";
+
+/// The most values firestore accepts in a single `in` filter
+const MAX_IN_VALUES: usize = 30;
+
+/// The most writes firestore accepts in a single commit
+const MAX_BATCH_WRITES: usize = 500;
+
+/// The stored id of the collection `copy_to()` and `move_to()` write into, refusing the collection the object is already in
+fn transfer_target(cfg: &CLConfig, source: &str, target_collection: &str) -> Result<String, CloudSyncError> {
+    let target = CLConfig { collection: target_collection.to_string(), ..cfg.clone() }.collection_id();
+    if target == source {
+        return Err(CloudSyncError::Unsupported(format!("`{}` is already the object's collection", target)));
+    }
+    Ok(target)
+}
+
+/// The longest document id firestore accepts, in bytes
+const MAX_DOC_ID_BYTES: usize = 1500;
+
+/// Check an id against firestore's rules for document ids before it's sent, so a bad uuid fails with `InvalidDocumentId`
+/// instead of an obscure error from deep in the client (or, with a `/`, silently addressing a document in a subcollection)
+fn check_doc_id(id: &str) -> Result<(), CloudSyncError> {
+    let reserved = id.len() > 4 && id.starts_with("__") && id.ends_with("__");
+    if id.is_empty() || id.len() > MAX_DOC_ID_BYTES || id.contains('/') || id == "." || id == ".." || reserved {
+        return Err(CloudSyncError::InvalidDocumentId(id.to_string()));
     }
+    Ok(())
+}
 
-    impl CloudSync<String> for TestOBJ {
-        fn config() -> CLConfig {
-            CLConfig {
-                project_id: "cloudsync-testing".to_string(),
-                cred_path: "./firebase.json".to_string(),
-                collection: "testing".to_string(),
-            }
+/// Build a write that fully overwrites (or creates) the document with this id
+fn set_write<S: Serialize>(cfg: &CLConfig, parent: &str, collection: &str, id: &str, obj: &S) -> Result<Write, CloudSyncError> {
+    check_doc_id(id)?;
+    Ok(doc_write(serialize_doc(cfg, &doc_path(parent, collection, id), obj)?))
+}
+
+/// Serialize an object into the document at this full path, writing `None` fields as nulls or leaving them out as `write_nulls` says
+/// Fails with `DocumentTooLarge` if the document comes out bigger than `max_document_bytes`, before anything is sent
+fn serialize_doc<S: Serialize>(cfg: &CLConfig, path: &str, obj: &S) -> Result<Document, CloudSyncError> {
+    let mut doc = serialize_with_nulls(path, obj)?;
+    set_nulls(&mut doc.fields, cfg.write_nulls);
+    let bytes = doc_size(&doc);
+    if bytes > cfg.max_document_bytes {
+        return Err(CloudSyncError::DocumentTooLarge { id: doc_id_of(path).to_string(), bytes });
+    }
+    Ok(doc)
+}
+
+/// Turn the empty values serde leaves for `None` into explicit nulls, or remove them (down into nested maps) when `write_nulls` is off
+/// Array elements can't be left out, so they always become nulls
+fn set_nulls(fields: &mut HashMap<String, Value>, write_nulls: bool) {
+    fn set_null(value: &mut Value) {
+        match &mut value.value_type {
+            None => value.value_type = Some(value::ValueType::NullValue(0)),
+            Some(value::ValueType::ArrayValue(array)) => array.values.iter_mut().for_each(set_null),
+            Some(value::ValueType::MapValue(map)) => map.fields.values_mut().for_each(set_null),
+            Some(_) => {}
+        }
+    }
+    if write_nulls {
+        fields.values_mut().for_each(set_null);
+        return;
+    }
+    fields.retain(|_, value| !is_null(value));
+    for value in fields.values_mut() {
+        match &mut value.value_type {
+            Some(value::ValueType::MapValue(map)) => set_nulls(&mut map.fields, false),
+            Some(value::ValueType::ArrayValue(array)) => array.values.iter_mut().for_each(set_null),
+            _ => {}
         }
     }
+}
 
-    impl Unique<String> for TestOBJ {
-        fn uuid(&self) -> String {
-            return String::from(&self.key);
+/// Build a write that replaces the document with this already serialized one
+/// Pending `ServerTimestamp` fields are taken out of the document and set by the server instead
+fn doc_write(mut doc: Document) -> Write {
+    Write {
+        update_mask: None,
+        update_transforms: server_timestamps(&mut doc.fields, ""),
+        current_document: None,
+        operation: Some(write::Operation::Update(doc)),
+    }
+}
+
+/// Take every pending `ServerTimestamp` out of these document fields (descending into maps),
+/// returning the transforms that set each one to the server's time
+fn server_timestamps(fields: &mut HashMap<String, Value>, prefix: &str) -> Vec<FieldTransform> {
+    let pending = |value: &Value| matches!(&value.value_type, Some(value::ValueType::StringValue(s)) if s == SERVER_TIMESTAMP_SENTINEL);
+    let mut transforms = vec![];
+    for (name, value) in fields.iter_mut() {
+        if let Some(value::ValueType::MapValue(map)) = &mut value.value_type {
+            transforms.extend(server_timestamps(&mut map.fields, &field_path(prefix, name)));
+        } else if pending(value) {
+            transforms.push(FieldTransform {
+                field_path: field_path(prefix, name),
+                transform_type: Some(field_transform::TransformType::SetToServerValue(field_transform::ServerValue::RequestTime as i32)),
+            });
         }
     }
+    fields.retain(|_, value| !pending(value));
+    transforms
+}
 
-    // Super basic test...
-    // Add more at a later time?
-    #[tokio::test]
-    async fn testSavingObject() {
-        let obj = TestOBJ {
-            key: "aaa".to_string(),
-            data: "data".to_string(),
+/// The path of the field `name` within the map at `prefix`, backtick quoting the name unless it's a plain identifier
+fn field_path(prefix: &str, name: &str) -> String {
+    let simple = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    let segment = if simple { name.to_string() } else { format!("`{}`", name) };
+    if prefix.is_empty() { segment } else { format!("{}.{}", prefix, segment) }
+}
+
+/// The path of every leaf field in these document fields, descending into maps, as used by a merging write's update mask
+fn merge_paths(fields: &HashMap<String, Value>, prefix: &str) -> Vec<String> {
+    let mut paths = vec![];
+    for (name, value) in fields {
+        let path = field_path(prefix, name);
+        match &value.value_type {
+            Some(value::ValueType::MapValue(map)) if !map.fields.is_empty() => paths.extend(merge_paths(&map.fields, &path)),
+            _ => paths.push(path),
+        }
+    }
+    paths
+}
+
+/// Nest a map of dot-notated field paths and their values into the document shape they describe,
+/// alongside the update mask naming each path, failing with `InvalidField` for a path with an empty segment
+fn nest_patch(fields: HashMap<String, serde_json::Value>) -> Result<(serde_json::Value, Vec<String>), CloudSyncError> {
+    let mut root = serde_json::Map::new();
+    let mut mask = vec![];
+    for (path, value) in fields {
+        let segments: Vec<&str> = path.split('.').collect();
+        if segments.iter().any(|segment| segment.is_empty()) {
+            return Err(CloudSyncError::InvalidField(path));
+        }
+        mask.push(segments.iter().fold(String::new(), |prefix, name| field_path(&prefix, name)));
+        let (last, parents) = segments.split_last().unwrap();
+        let mut map = &mut root;
+        for name in parents {
+            let entry = map.entry(name.to_string()).or_insert_with(|| serde_json::Value::Object(Default::default()));
+            if !entry.is_object() {
+                *entry = serde_json::Value::Object(Default::default());
+            }
+            map = entry.as_object_mut().unwrap();
+        }
+        map.insert(last.to_string(), value);
+    }
+    Ok((serde_json::Value::Object(root), mask))
+}
+
+/// Build a write that deletes the document with this id
+fn delete_write(parent: &str, collection: &str, id: &str) -> Result<Write, CloudSyncError> {
+    check_doc_id(id)?;
+    Ok(delete_doc_write(doc_path(parent, collection, id)))
+}
+
+/// Build a write that deletes the document with this full name, as read back from firestore
+fn delete_doc_write(name: String) -> Write {
+    Write {
+        update_mask: None,
+        update_transforms: vec![],
+        current_document: None,
+        operation: Some(write::Operation::Delete(name)),
+    }
+}
+
+/// Build a write that only applies these field transforms to the document with this id, creating it if it doesn't exist
+fn transform_write(parent: &str, collection: &str, id: &str, transforms: Vec<FieldTransform>) -> Result<Write, CloudSyncError> {
+    check_doc_id(id)?;
+    Ok(Write {
+        update_mask: Some(DocumentMask { field_paths: vec![] }),
+        update_transforms: transforms,
+        current_document: None,
+        operation: Some(write::Operation::Update(Document { name: doc_path(parent, collection, id), ..Default::default() })),
+    })
+}
+
+/// Atomically add (or with `remove`, take out) these values to the array `field` of the document with this id
+async fn commit_array_transform<B: Backend>(cfg: &CLConfig, id: &str, field: &str, values: Vec<Value>, remove: bool) -> Result<(), CloudSyncError> {
+    let backend = B::connect(cfg).await?;
+    let is_array = |value: &value::ValueType| matches!(value, value::ValueType::ArrayValue(_));
+    check_field_type(&backend, cfg, id, field, "an array", is_array).await?;
+    let values = ArrayValue { values };
+    let transform = FieldTransform {
+        field_path: field.to_string(),
+        transform_type: Some(if remove {
+            field_transform::TransformType::RemoveAllFromArray(values)
+        } else {
+            field_transform::TransformType::AppendMissingElements(values)
+        }),
+    };
+    let write = transform_write(&cfg.parent(backend.documents_path()), &cfg.collection_id(), id, vec![transform])?;
+    with_retry!(&cfg, backend.commit(vec![write.clone()]))
+}
+
+/// Fail with `WrongFieldType` if the stored document holds a value in `field` that `accepts` rejects
+/// A missing document, missing field or null are all accepted, since transforms treat them as empty
+async fn check_field_type<B: Backend>(
+    backend: &B,
+    cfg: &CLConfig,
+    id: &str,
+    field: &str,
+    expected: &str,
+    accepts: fn(&value::ValueType) -> bool,
+) -> Result<(), CloudSyncError> {
+    let doc = fetch_doc(backend, cfg, &cfg.parent(backend.documents_path()), &cfg.collection_id(), id).await?;
+    match doc.and_then(|doc| memory::field_value(&doc, field)).and_then(|value| value.value_type) {
+        Some(value::ValueType::NullValue(_)) | None => Ok(()),
+        Some(value_type) if accepts(&value_type) => Ok(()),
+        Some(_) => Err(CloudSyncError::WrongFieldType { field: field.to_string(), expected: expected.to_string() }),
+    }
+}
+
+/// Fully overwrite (or create) the document with this id with an object, untraced so operations that save as one step report only themselves
+async fn save_doc<B: Backend, S: Serialize + Sync>(cfg: &CLConfig, collection: &str, id: &str, obj: &S) -> Result<(), CloudSyncError> {
+    let backend = B::connect(cfg).await?;
+    let parent = cfg.parent(backend.documents_path());
+    let write = set_write(cfg, &parent, collection, id, obj)?;
+    with_retry!(cfg, backend.commit(vec![write.clone()]))
+}
+
+/// Read the config's whole collection, capped at its `default_query_limit`, untraced for the operations built on it
+async fn read_all<S, B: Backend>(backend: &B, cfg: &CLConfig) -> Result<Vec<S>, CloudSyncError>
+where S: for<'a> Deserialize<'a> + Send + 'static {
+    let parent = cfg.parent(backend.documents_path());
+    let docs = with_retry!(cfg, backend.query(read_params(cfg, &parent)))?;
+    deserialize_docs(cfg, docs).await
+}
+
+/// Read up to `limit` objects ordered by document id, starting just after the document `cursor` when there is one
+async fn read_page<S: for<'a> Deserialize<'a>, B: Backend>(cfg: &CLConfig, limit: u32, cursor: Option<&str>) -> Result<Vec<S>, CloudSyncError> {
+    let backend = B::connect(cfg).await?;
+    let parent = cfg.parent(backend.documents_path());
+    let mut params = query_params(&parent, &cfg.collection_id())
+        .with_order_by(vec![FirestoreQueryOrder::new(DOC_ID_FIELD.to_string(), FirestoreQueryDirection::Ascending)])
+        .with_limit(limit);
+    if let Some(cursor) = cursor {
+        params = params.with_start_at(FirestoreQueryCursor::AfterValue(vec![doc_ref(&parent, &cfg.collection_id(), cursor)?]));
+    }
+    from_docs(&with_retry!(cfg, backend.query(params.clone()))?)
+}
+
+/// Read the object with this id along with its document's metadata, or `None` if it doesn't exist
+async fn read_with_meta<S: for<'a> Deserialize<'a>, B: Backend>(cfg: &CLConfig, id: &str) -> Result<Option<(S, DocMeta)>, CloudSyncError> {
+    let backend = B::connect(cfg).await?;
+    let parent = cfg.parent(backend.documents_path());
+    let doc = match fetch_doc(&backend, cfg, &parent, &cfg.collection_id(), id).await? {
+        Some(doc) => doc,
+        None => return Ok(None),
+    };
+    let meta = DocMeta {
+        path: doc.name.clone(),
+        id: doc_id_of(&doc.name).to_string(),
+        create_time: doc.create_time.clone().map(from_timestamp).transpose()?.unwrap_or_default(),
+        update_time: doc.update_time.clone().map(from_timestamp).transpose()?.unwrap_or_default(),
+    };
+    Ok(Some((deserialize_doc(&doc)?, meta)))
+}
+
+/// Listen to the config's collection, turning the backend's document changes into `ChangeEvent`s for `listen()` and `watch()`
+/// The listener's initial snapshot comes through first, so every object already stored starts out as `Added`
+async fn change_events<S, T, B>(cfg: &CLConfig) -> Result<BoxStream<'static, Result<ChangeEvent<S, T>, CloudSyncError>>, CloudSyncError>
+where
+    S: for<'a> Deserialize<'a> + Unique<T> + Send + 'static,
+    T: Serialize + Send + 'static,
+    B: Backend,
+{
+    let backend = B::connect(cfg).await?;
+    let parent = cfg.parent(backend.documents_path());
+    let changes = backend.listen(query_params(&parent, &cfg.collection_id()), cfg.retry.clone()).await?;
+    // Remember the uuid of every object seen so far, to tell new objects from changed ones and to name removed ones
+    let mut known: HashMap<String, T> = HashMap::new();
+    Ok(changes.filter_map(move |change| {
+        let event = match change {
+            Ok(DocChange::Changed(doc)) => match deserialize_doc::<S>(&doc) {
+                Ok(obj) => match known.insert(doc.name, obj.uuid()) {
+                    Some(_) => Some(Ok(ChangeEvent::Modified(obj))),
+                    None => Some(Ok(ChangeEvent::Added(obj))),
+                },
+                Err(err) => Some(Err(err.into())),
+            },
+            Ok(DocChange::Removed(name)) => known.remove(&name).map(|id| Ok(ChangeEvent::Removed(id))),
+            Err(err) => Some(Err(err)),
         };
-        obj.save().await;
-        let vec = TestOBJ::get().await.unwrap();
-        assert_eq!(vec.len(), 1);
+        futures::future::ready(event)
+    }).boxed())
+}
+
+/// Read the document with this id, or `None` if it doesn't exist, failing with `InvalidDocumentId` before anything is sent
+async fn fetch_doc<B: Backend>(backend: &B, cfg: &CLConfig, parent: &str, collection: &str, id: &str) -> Result<Option<Document>, CloudSyncError> {
+    check_doc_id(id)?;
+    with_retry!(cfg, backend.get_doc(parent, collection, id))
+}
+
+/// Check whether a document exists without downloading any of its fields
+async fn doc_exists<B: Backend>(backend: &B, cfg: &CLConfig, parent: &str, collection: &str, id: &str) -> Result<bool, CloudSyncError> {
+    let params = query_params(parent, collection)
+        .with_filter(FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::Equal(
+            DOC_ID_FIELD.to_string(),
+            doc_ref(parent, collection, id)?,
+        ))))
+        .with_return_only_fields(vec![DOC_ID_FIELD.to_string()])
+        .with_limit(1);
+    Ok(!with_retry!(cfg, backend.query(params.clone()))?.is_empty())
+}
+
+/// Turn firestore's rejection of a query that has no composite index yet into `MissingIndex`,
+/// carrying the console link firestore includes for creating that index
+fn missing_index(err: CloudSyncError) -> CloudSyncError {
+    match &err {
+        CloudSyncError::Firestore(FirestoreError::DatabaseError(db_err))
+            if db_err.public.code == "FailedPrecondition" && db_err.details.contains("requires an index") =>
+        {
+            let url = db_err.details.split_whitespace().find(|word| word.starts_with("https://"));
+            CloudSyncError::MissingIndex(url.map(|url| url.trim_end_matches(['.', ',', '"', '\'']).to_string()))
+        }
+        _ => err,
+    }
+}
+
+/// Find the one field the range filters among `filters` are on, if any
+/// Firestore only allows range (and `!=` / `not-in`) filters on a single field, so filters on several fail with `InvalidQuery`
+fn range_field(filters: &[Filter]) -> Result<Option<&str>, CloudSyncError> {
+    let mut fields = filters.iter().filter(|filter| filter.op.is_range()).map(|filter| filter.field.as_str());
+    let first = fields.next();
+    match fields.find(|field| Some(*field) != first) {
+        Some(other) => Err(CloudSyncError::InvalidQuery(format!(
+            "range filters must all be on the same field, but they are on both `{}` and `{}`",
+            first.unwrap_or_default(),
+            other,
+        ))),
+        None => Ok(first),
+    }
+}
+
+/// Delete every document in the config's collection in batches, returning how many were removed
+/// With `dry_run` on, the documents are only logged and counted
+#[cfg(any(feature = "dangerous", feature = "test-util"))]
+async fn clear_docs<B: Backend>(backend: &B, cfg: &CLConfig) -> Result<usize, CloudSyncError> {
+    let parent = cfg.parent(backend.documents_path());
+    let params = query_params(&parent, &cfg.collection_id()).with_return_only_fields(vec![DOC_ID_FIELD.to_string()]);
+    if cfg.dry_run {
+        let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+        docs.iter().for_each(|doc| trace::log_dry_run_delete(&doc.name));
+        return Ok(docs.len());
+    }
+    let params = params.with_limit(MAX_BATCH_WRITES as u32);
+    let mut removed = 0;
+    loop {
+        let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+        if docs.is_empty() {
+            return Ok(removed);
+        }
+        let deletes: Vec<Write> = docs.into_iter().map(|doc| delete_doc_write(doc.name)).collect();
+        let count = deletes.len();
+        if let Err(err) = with_retry!(&cfg, backend.commit(deletes.clone())) {
+            return Err(CloudSyncError::Batch { committed: removed, source: Box::new(err) });
+        }
+        removed += count;
+    }
+}
+
+/// Commit a write that may only touch a document that already exists, failing with `NotFound` otherwise
+async fn commit_existing<B: Backend>(backend: &B, cfg: &CLConfig, collection: &str, id: &str, write: Write) -> Result<(), CloudSyncError> {
+    let write = Write {
+        current_document: Some(Precondition { condition_type: Some(precondition::ConditionType::Exists(true)) }),
+        ..write
+    };
+    match with_retry!(&cfg, backend.commit(vec![write.clone()])) {
+        Err(CloudSyncError::Firestore(FirestoreError::DataNotFoundError(_))) => {
+            Err(CloudSyncError::NotFound { collection: collection.to_string(), id: id.to_string() })
+        }
+        result => result,
+    }
+}
+
+/// Commit a group of deletes, or with `dry_run` on just log the documents they would remove and leave them in place
+async fn commit_deletes<B: Backend>(backend: &B, cfg: &CLConfig, deletes: Vec<Write>) -> Result<(), CloudSyncError> {
+    if cfg.dry_run {
+        for delete in &deletes {
+            if let Some(write::Operation::Delete(name)) = &delete.operation {
+                trace::log_dry_run_delete(name);
+            }
+        }
+        return Ok(());
+    }
+    with_retry!(&cfg, backend.commit(deletes.clone()))
+}
+
+/// The dry run of a delete that has to find its document: logs the document if it exists, and returns whether it does
+async fn dry_run_existing<B: Backend>(backend: &B, cfg: &CLConfig, parent: &str, collection: &str, id: &str) -> Result<bool, CloudSyncError> {
+    let exists = doc_exists(backend, cfg, parent, collection, id).await?;
+    if exists {
+        trace::log_dry_run_delete(&doc_path(parent, collection, id));
+    }
+    Ok(exists)
+}
+
+/// Whether a conditional write was rejected because its precondition didn't hold
+fn is_precondition_failure(err: &CloudSyncError) -> bool {
+    match err {
+        CloudSyncError::Firestore(FirestoreError::DataConflictError(_)) => true,
+        CloudSyncError::Firestore(FirestoreError::DatabaseError(db_err)) => db_err.public.code == "FailedPrecondition",
+        _ => false,
+    }
+}
+
+/// Allows a serializable object to be saved in the cloud using firestore
+/// The storage itself goes through a `Backend`, which is the real firestore database unless another one is named with `B`
+#[async_trait]
+pub trait CloudSync<T, B = FirestoreBackend> where
+    for<'a> Self: Deserialize<'a> + Serialize + Unique<T> + Sync + Send + 'static,
+    T: Serialize + ToDocId + std::cmp::Eq + std::hash::Hash + Send + Sync,
+    B: Backend {
+
+    /// Save an object to this object's collection
+    /// Any existing document with the same uuid is fully overwritten in a single write
+    async fn save(&self) -> Result<(), CloudSyncError> {
+        trace::traced("save", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            save_doc::<B, _>(&Self::config(), &self.collection(), &self.doc_id(), self).await
+        }).await
+    }
+
+    /// Save a copy of this object into `target_collection`, under the same document id, leaving its own collection untouched
+    /// The target is named like the config's `collection`, so the config's `parent_path` and `collection_prefix` apply to it too
+    /// Fails with `Unsupported` if the target is the collection the object is already saved to
+    async fn copy_to(&self, target_collection: &str) -> Result<(), CloudSyncError> {
+        trace::traced("copy_to", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let target = transfer_target(&cfg, &self.collection(), target_collection)?;
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let write = set_write(&cfg, &parent, &target, &self.doc_id(), self)?;
+            with_retry!(&cfg, backend.commit(vec![write.clone()]))
+        }).await
+    }
+
+    /// Move this object into `target_collection`: save it there and delete it from its own collection in one atomic commit,
+    /// so it's never in both or neither. The target is named like in `copy_to()`
+    /// Fails with `Unsupported` if the target is the collection the object is already saved to
+    async fn move_to(&self, target_collection: &str) -> Result<(), CloudSyncError> {
+        trace::traced("move_to", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let target = transfer_target(&cfg, &self.collection(), target_collection)?;
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let writes = vec![
+                set_write(&cfg, &parent, &target, &self.doc_id(), self)?,
+                delete_write(&parent, &self.collection(), &self.doc_id())?,
+            ];
+            with_retry!(&cfg, backend.commit(writes.clone()))
+        }).await
+    }
+
+    /// Save an object under the document id `id` rather than the one its uuid maps to, e.g. when importing legacy data
+    /// Like `save()`, any existing document with that id is fully overwritten; nothing saved under the uuid's id is touched
+    async fn save_with_id(&self, id: &str) -> Result<(), CloudSyncError> {
+        trace::traced("save_with_id", &self.collection(), Some(id), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let write = set_write(&cfg, &parent, &self.collection(), id, self)?;
+            with_retry!(&cfg, backend.commit(vec![write.clone()]))
+        }).await
+    }
+
+    /// Save an object and read it back, returning the version that was actually persisted
+    /// Use this when server side transforms or triggers change the document, so the caller isn't left holding a stale copy
+    async fn save_and_read(&self) -> Result<Self, CloudSyncError> {
+        trace::traced("save_and_read", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let (collection, id) = (self.collection(), self.doc_id());
+            save_doc::<B, _>(&cfg, &collection, &id, self).await?;
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            match fetch_doc(&backend, &cfg, &parent, &collection, &id).await? {
+                Some(doc) => Ok(deserialize_doc(&doc)?),
+                // Only possible if someone deleted the document between the write and the read
+                None => Err(CloudSyncError::NotFound { collection, id }),
+            }
+        }).await
+    }
+
+    /// Save this object only if no object with the same uuid is saved yet
+    /// The existence check and the write happen atomically on the server, so this works as a race-free claim:
+    /// returns `true` if this call created the document and `false` if one already existed
+    async fn save_if_absent(&self) -> Result<bool, CloudSyncError> {
+        trace::traced("save_if_absent", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let create = Write {
+                current_document: Some(Precondition { condition_type: Some(precondition::ConditionType::Exists(false)) }),
+                ..set_write(&cfg, &parent, &self.collection(), &self.doc_id(), self)?
+            };
+            match with_retry!(&cfg, backend.commit(vec![create.clone()])) {
+                Ok(()) => Ok(true),
+                Err(err) if is_precondition_failure(&err) => Ok(false),
+                Err(err) => Err(err),
+            }
+        }).await
+    }
+
+    /// Save this object only if its stored document was last updated at `expected_update_time`
+    /// Use `get_with_update_time()` to read that time; if someone else wrote in between this returns `Conflict`, so the caller can re-read and retry
+    async fn save_checked(&self, expected_update_time: DateTime<Utc>) -> Result<(), CloudSyncError> {
+        trace::traced("save_checked", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let (collection, id) = (self.collection(), self.doc_id());
+            let write = Write {
+                current_document: Some(Precondition {
+                    condition_type: Some(precondition::ConditionType::UpdateTime(to_timestamp(expected_update_time))),
+                }),
+                ..set_write(&cfg, &parent, &collection, &id, self)?
+            };
+            match with_retry!(&cfg, backend.commit(vec![write.clone()])) {
+                Err(err) if is_precondition_failure(&err) => Err(CloudSyncError::Conflict { collection, id }),
+                Err(CloudSyncError::Firestore(FirestoreError::DataNotFoundError(_))) => Err(CloudSyncError::NotFound { collection, id }),
+                result => result,
+            }
+        }).await
+    }
+
+    /// Save this object only if its stored document still holds exactly `previous`, a compare-and-swap on the whole object
+    /// The stored content is compared in its serialized form, and the write is conditioned on the document not being updated
+    /// since that comparison, so a concurrent writer can't slip in between. If the content differs this returns `Conflict`,
+    /// and `NotFound` if the document is gone
+    async fn save_if_matches(&self, previous: &Self) -> Result<(), CloudSyncError> {
+        trace::traced("save_if_matches", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let (collection, id) = (self.collection(), self.doc_id());
+            let stored = match fetch_doc(&backend, &cfg, &parent, &collection, &id).await? {
+                Some(doc) => doc,
+                None => return Err(CloudSyncError::NotFound { collection, id }),
+            };
+            if serialize_doc(&cfg, &stored.name, previous)?.fields != stored.fields {
+                return Err(CloudSyncError::Conflict { collection, id });
+            }
+            let condition = match stored.update_time {
+                Some(update_time) => precondition::ConditionType::UpdateTime(update_time),
+                None => precondition::ConditionType::Exists(true),
+            };
+            let write = Write {
+                current_document: Some(Precondition { condition_type: Some(condition) }),
+                ..set_write(&cfg, &parent, &collection, &id, self)?
+            };
+            match with_retry!(&cfg, backend.commit(vec![write.clone()])) {
+                Err(err) if is_precondition_failure(&err) => Err(CloudSyncError::Conflict { collection, id }),
+                Err(CloudSyncError::Firestore(FirestoreError::DataNotFoundError(_))) => Err(CloudSyncError::NotFound { collection, id }),
+                result => result,
+            }
+        }).await
+    }
+
+    /// Save an object by merging it into its stored document: every field in this object's serialized form is written
+    /// (down into nested maps), while fields only the stored document has are left as they are
+    /// Unlike `save()`, which replaces the whole document, this lets several structs that are views of one document write to it
+    /// `None` fields clear the stored value, unless `write_nulls` is off, in which case they're left as they are
+    async fn save_merge(&self) -> Result<(), CloudSyncError> {
+        trace::traced("save_merge", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let write = set_write(&cfg, &parent, &self.collection(), &self.doc_id(), self)?;
+            let paths = match &write.operation {
+                Some(write::Operation::Update(doc)) => merge_paths(&doc.fields, ""),
+                _ => vec![],
+            };
+            let write = Write { update_mask: Some(DocumentMask { field_paths: paths }), ..write };
+            with_retry!(&cfg, backend.commit(vec![write.clone()]))
+        }).await
+    }
+
+    /// Save an object keyed by a `uuid::Uuid`, first giving it a fresh random (v4) uuid if its uuid is still nil
+    /// Only available with the `uuid` feature
+    #[cfg(feature = "uuid")]
+    async fn save_with_new_uuid(&mut self) -> Result<(), CloudSyncError> where Self: AssignUuid {
+        if Unique::<uuid::Uuid>::uuid(self).is_nil() {
+            self.set_uuid(uuid::Uuid::new_v4());
+        }
+        self.save().await
+    }
+
+    /// Save an object, stamping its updated time on every write and its created time only when it isn't saved yet
+    async fn save_timestamped(&mut self) -> Result<(), CloudSyncError> where Self: Timestamped {
+        let (collection, id) = (self.collection(), self.doc_id());
+        trace::traced("save_timestamped", &collection, Some(&id), Self::config().operation_timeout, async {
+            let now = Utc::now();
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            if !doc_exists(&backend, &cfg, &parent, &collection, &id).await? {
+                self.set_created(now);
+            }
+            self.set_updated(now);
+            save_doc::<B, _>(&cfg, &collection, &id, &*self).await
+        }).await
+    }
+
+    /// Save many objects at once, grouping the writes into batches of at most 500
+    /// Each batch is committed atomically, and on failure the error reports how many objects were already committed
+    async fn save_batch(objs: &[Self]) -> Result<(), CloudSyncError> {
+        trace::traced("save_batch", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let mut committed = 0;
+            for chunk in objs.chunks(MAX_BATCH_WRITES) {
+                let writes = chunk.iter().map(|obj| set_write(&cfg, &parent, &obj.collection(), &obj.doc_id(), obj));
+                let result = match writes.collect::<Result<Vec<_>, _>>() {
+                    Ok(writes) => with_retry!(&cfg, backend.commit(writes.clone())),
+                    Err(err) => Err(err),
+                };
+                if let Err(err) = result {
+                    return Err(CloudSyncError::Batch { committed, source: Box::new(err) });
+                }
+                committed += chunk.len();
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Save every object a stream yields, in batches of at most 500 with up to `concurrency` batches committing at once,
+    /// returning how many were saved. Only the batches being written are held in memory, so a large feed can be imported without collecting it first
+    /// On failure the error reports how many objects were already committed; batches still in flight at that point may or may not have been written
+    async fn save_stream<S>(objs: S, concurrency: usize) -> Result<usize, CloudSyncError> where S: Stream<Item = Self> + Send + 'async_trait {
+        trace::traced("save_stream", &Self::config().collection_id(), None, Self::config().operation_timeout, async move {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let (cfg, backend) = (&cfg, &backend);
+            let commits = objs.chunks(MAX_BATCH_WRITES)
+                .map(|chunk| {
+                    let writes = chunk.iter()
+                        .map(|obj| set_write(cfg, &parent, &obj.collection(), &obj.doc_id(), obj))
+                        .collect::<Result<Vec<_>, _>>();
+                    async move {
+                        let writes = writes?;
+                        with_retry!(&cfg, backend.commit(writes.clone()))?;
+                        Ok(writes.len())
+                    }
+                })
+                .buffer_unordered(concurrency.max(1));
+            let mut commits = std::pin::pin!(commits);
+            let mut committed = 0;
+            while let Some(result) = commits.next().await {
+                match result {
+                    Ok(count) => committed += count,
+                    Err(err) => return Err(CloudSyncError::Batch { committed, source: Box::new(err) }),
+                }
+            }
+            Ok(committed)
+        }).await
+    }
+
+    /// Save all of these objects in a single atomic commit, so either every one of them is written or none is
+    /// Firestore caps a commit at 500 writes, so more objects than that fail with `TooManyWrites` before anything is written
+    async fn save_transactional(objs: &[Self]) -> Result<(), CloudSyncError> {
+        trace::traced("save_transactional", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            if objs.len() > MAX_BATCH_WRITES {
+                return Err(CloudSyncError::TooManyWrites { count: objs.len(), max: MAX_BATCH_WRITES });
+            }
+            if objs.is_empty() {
+                return Ok(());
+            }
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let writes = objs.iter()
+                .map(|obj| set_write(&cfg, &parent, &obj.collection(), &obj.doc_id(), obj))
+                .collect::<Result<Vec<_>, _>>()?;
+            with_retry!(&cfg, backend.commit(writes.clone()))
+        }).await
+    }
+
+    /// Make the collection hold exactly these objects, writing only the ones that are new or differ from what's stored
+    /// and deleting stored documents that aren't among them, returning a count of what was done
+    /// Objects are compared by their serialized content, and the writes are committed in batches of at most 500
+    ///
+    /// Like `save()`, each object is written to its own `collection()`, so with that overridden the config's collection
+    /// and every collection the objects name are synced together
+    async fn sync(objs: &[Self]) -> Result<SyncReport, CloudSyncError> {
+        trace::traced("sync", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let mut collections: BTreeSet<String> = objs.iter().map(|obj| obj.collection()).collect();
+            collections.insert(cfg.collection_id());
+            let mut stored: HashMap<String, Document> = HashMap::new();
+            for collection in &collections {
+                let docs = with_retry!(&cfg, backend.query(query_params(&parent, collection)))?;
+                stored.extend(docs.into_iter().map(|doc| (doc.name.clone(), doc)));
+            }
+
+            let mut report = SyncReport::default();
+            let mut writes = vec![];
+            for obj in objs {
+                let doc = serialize_doc(&cfg, &doc_path(&parent, &obj.collection(), &obj.doc_id()), obj)?;
+                match stored.remove(&doc.name) {
+                    Some(existing) if existing.fields == doc.fields => report.unchanged += 1,
+                    Some(_) => {
+                        report.updated += 1;
+                        writes.push(doc_write(doc));
+                    }
+                    None => {
+                        report.created += 1;
+                        writes.push(doc_write(doc));
+                    }
+                }
+            }
+            report.deleted = stored.len();
+            writes.extend(stored.into_keys().map(delete_doc_write));
+
+            let mut committed = 0;
+            for chunk in writes.chunks(MAX_BATCH_WRITES) {
+                if let Err(err) = with_retry!(&cfg, backend.commit(chunk.to_vec())) {
+                    return Err(CloudSyncError::Batch { committed, source: Box::new(err) });
+                }
+                committed += chunk.len();
+            }
+            Ok(report)
+        }).await
+    }
+
+    /// Read every object in the collection, pass it through `f` and write the result back to the same document, returning how many were migrated
+    /// The collection is streamed and written back in batches of at most 500; on failure the error reports how many were already written
+    async fn migrate<F>(mut f: F) -> Result<usize, CloudSyncError> where F: FnMut(Self) -> Self + Send + 'async_trait, Self: 'static {
+        Self::migrate_async(move |obj| futures::future::ready(f(obj))).await
+    }
+
+    /// Like `migrate()`, but with a transform that has to await something, e.g. a lookup elsewhere
+    async fn migrate_async<F, Fut>(mut f: F) -> Result<usize, CloudSyncError>
+    where
+        F: FnMut(Self) -> Fut + Send + 'async_trait,
+        Fut: std::future::Future<Output = Self> + Send,
+        Self: 'static,
+    {
+        trace::traced("migrate", &Self::config().collection_id(), None, Self::config().operation_timeout, async move {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let mut docs = with_retry!(&cfg, backend.query_stream(query_params(&parent, &cfg.collection_id())))?;
+            let mut migrated = 0;
+            let mut writes = vec![];
+            loop {
+                let doc = docs.next().await.transpose()?;
+                let done = doc.is_none();
+                if let Some(doc) = doc {
+                    let obj = f(deserialize_doc(&doc)?).await;
+                    writes.push(doc_write(serialize_doc(&cfg, &doc.name, &obj)?));
+                }
+                if writes.len() == MAX_BATCH_WRITES || (done && !writes.is_empty()) {
+                    if let Err(err) = with_retry!(&cfg, backend.commit(writes.clone())) {
+                        return Err(CloudSyncError::Batch { committed: migrated, source: Box::new(err) });
+                    }
+                    migrated += writes.len();
+                    writes.clear();
+                }
+                if done {
+                    return Ok(migrated);
+                }
+            }
+        }).await
+    }
+
+    /// Update only the named top-level fields of this object's document, leaving all other stored fields untouched
+    /// Returns an error naming the first field that isn't part of this object's serialized form
+    async fn update(&self, fields: &[&str]) -> Result<(), CloudSyncError> {
+        trace::traced("update", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let doc = serialize_with_nulls("", self)?;
+            if let Some(bad) = fields.iter().find(|field| !doc.fields.contains_key(**field)) {
+                return Err(CloudSyncError::InvalidField(bad.to_string()));
+            }
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let write = Write {
+                update_mask: Some(DocumentMask { field_paths: fields.iter().map(|field| field.to_string()).collect() }),
+                ..set_write(&cfg, &parent, &self.collection(), &self.doc_id(), self)?
+            };
+            with_retry!(&cfg, backend.commit(vec![write.clone()]))
+        }).await
+    }
+
+    /// Set the given fields on the stored object with this uuid, leaving every other stored field untouched
+    /// Keys are field paths, with dots reaching into nested maps (e.g. `"address.city"`), so fields only known at runtime can be patched;
+    /// this is the dynamic counterpart of `update()`. Fails with `NotFound` if the object isn't saved
+    async fn patch(id: &T, fields: HashMap<String, serde_json::Value>) -> Result<(), CloudSyncError> {
+        trace::traced("patch", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async move {
+            let (patch, mask) = nest_patch(fields)?;
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let id = Self::doc_id_for(id);
+            let write = Write {
+                update_mask: Some(DocumentMask { field_paths: mask }),
+                ..set_write(&cfg, &parent, &cfg.collection_id(), &id, &patch)?
+            };
+            commit_existing(&backend, &cfg, &cfg.collection_id(), &id, write).await
+        }).await
+    }
+
+    /// Remove this object from its collection
+    async fn rm(&self) -> Result<(), CloudSyncError> {
+        trace::traced("rm", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let delete = delete_write(&parent, &self.collection(), &self.doc_id())?;
+            commit_deletes(&backend, &cfg, vec![delete]).await
+        }).await
+    }
+
+    /// Remove this object from its collection, returning whether it was there to remove (`false` if it was already gone)
+    /// The check and the delete are one conditional write, so two concurrent calls can't both report the removal
+    async fn rm_counted(&self) -> Result<bool, CloudSyncError> {
+        let (collection, id) = (self.collection(), self.doc_id());
+        trace::traced("rm_counted", &collection, Some(&id), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            if cfg.dry_run {
+                return dry_run_existing(&backend, &cfg, &parent, &collection, &id).await;
+            }
+            let delete = delete_write(&parent, &collection, &id)?;
+            match commit_existing(&backend, &cfg, &collection, &id, delete).await {
+                Ok(()) => Ok(true),
+                Err(CloudSyncError::NotFound { .. }) => Ok(false),
+                Err(err) => Err(err),
+            }
+        }).await
+    }
+
+    /// Remove the object with this uuid from the collection without fetching it first
+    /// Succeeds without doing anything if no such document exists
+    async fn rm_by_id(id: &T) -> Result<(), CloudSyncError> {
+        Self::collection_handle().rm_by_id(id).await
+    }
+
+    /// Remove the object with this uuid from the collection, returning `NotFound` if it doesn't exist
+    async fn rm_by_id_strict(id: &T) -> Result<(), CloudSyncError> {
+        trace::traced("rm_by_id_strict", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let id = Self::doc_id_for(id);
+            if cfg.dry_run {
+                return match dry_run_existing(&backend, &cfg, &parent, &cfg.collection_id(), &id).await? {
+                    true => Ok(()),
+                    false => Err(CloudSyncError::NotFound { collection: cfg.collection_id(), id }),
+                };
+            }
+            let delete = delete_write(&parent, &cfg.collection_id(), &id)?;
+            commit_existing(&backend, &cfg, &cfg.collection_id(), &id, delete).await
+        }).await
+    }
+
+    /// Mark this object as deleted by stamping its document's `deleted_at` field with the server's time, without removing it
+    /// `get_active()` leaves soft-deleted objects out and `restore()` brings them back; saving the object again also clears the mark,
+    /// since `deleted_at` isn't part of its serialized form. Fails with `NotFound` if the object isn't saved
+    async fn soft_rm(&self) -> Result<(), CloudSyncError> {
+        trace::traced("soft_rm", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let transform = FieldTransform {
+                field_path: DELETED_AT_FIELD.to_string(),
+                transform_type: Some(field_transform::TransformType::SetToServerValue(field_transform::ServerValue::RequestTime as i32)),
+            };
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let write = transform_write(&parent, &self.collection(), &self.doc_id(), vec![transform])?;
+            commit_existing(&backend, &cfg, &self.collection(), &self.doc_id(), write).await
+        }).await
+    }
+
+    /// Clear the `deleted_at` mark left by `soft_rm()`, so the object shows up in `get_active()` again
+    /// Fails with `NotFound` if the object isn't saved
+    async fn restore(&self) -> Result<(), CloudSyncError> {
+        trace::traced("restore", &self.collection(), Some(&self.doc_id()), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            // Masking a field the write doesn't hold removes it from the document
+            let write = Write {
+                update_mask: Some(DocumentMask { field_paths: vec![DELETED_AT_FIELD.to_string()] }),
+                ..transform_write(&parent, &self.collection(), &self.doc_id(), vec![])?
+            };
+            commit_existing(&backend, &cfg, &self.collection(), &self.doc_id(), write).await
+        }).await
+    }
+
+    /// Remove the objects with these uuids, grouping the deletes into batches of at most 500
+    /// Uuids that aren't stored are skipped without error; on failure the error reports how many deletes were already committed
+    async fn rm_batch(ids: &[T]) -> Result<(), CloudSyncError> {
+        trace::traced("rm_batch", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            // Built up front so a bad id can't fail the batch after earlier chunks were already removed
+            let deletes = ids.iter()
+                .map(|id| delete_write(&parent, &cfg.collection_id(), &Self::doc_id_for(id)))
+                .collect::<Result<Vec<Write>, _>>()?;
+            let mut committed = 0;
+            for chunk in deletes.chunks(MAX_BATCH_WRITES) {
+                if let Err(err) = commit_deletes(&backend, &cfg, chunk.to_vec()).await {
+                    return Err(CloudSyncError::Batch { committed, source: Box::new(err) });
+                }
+                committed += chunk.len();
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Delete every document whose `field` compares to `value` with `op`, returning how many were removed
+    /// The deletes are committed in batches of at most 500, and on failure the error reports how many were already removed
+    async fn rm_where(field: &str, op: FilterOp, value: impl Serialize + Send) -> Result<usize, CloudSyncError> {
+        trace::traced("rm_where", &Self::config().collection_id(), None, Self::config().operation_timeout, async move {
+            let filter = op.filter(field, query_value(&value)?);
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_filter(filter)
+                .with_return_only_fields(vec![DOC_ID_FIELD.to_string()]);
+            let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+            let mut removed = 0;
+            for chunk in docs.chunks(MAX_BATCH_WRITES) {
+                let deletes: Vec<Write> = chunk.iter().map(|doc| delete_doc_write(doc.name.clone())).collect();
+                if let Err(err) = commit_deletes(&backend, &cfg, deletes).await {
+                    return Err(CloudSyncError::Batch { committed: removed, source: Box::new(err) });
+                }
+                removed += chunk.len();
+            }
+            Ok(removed)
+        }).await
+    }
+
+    /// Delete every document in the config's collection, returning how many were removed
+    /// Only available with the `dangerous` feature, since there's no undo
+    #[cfg(feature = "dangerous")]
+    async fn clear_collection() -> Result<usize, CloudSyncError> {
+        trace::traced("clear_collection", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            clear_docs(&backend, &cfg).await
+        }).await
+    }
+
+    /// Check that the database can be reached with this object's config, by connecting and reading at most one document id
+    /// Meant as a readiness probe at startup: credentials, network or permission problems come back as the error
+    async fn ping() -> Result<(), CloudSyncError> {
+        trace::traced("ping", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_return_only_fields(vec![DOC_ID_FIELD.to_string()])
+                .with_limit(1);
+            with_retry!(&cfg, backend.query(params.clone()))?;
+            Ok(())
+        }).await
+    }
+
+    /// Check whether the collection holds no documents, reading at most one document id
+    /// Firestore has no empty collections, so this is also how to tell whether the collection exists at all
+    async fn is_empty() -> Result<bool, CloudSyncError> {
+        trace::traced("is_empty", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_return_only_fields(vec![DOC_ID_FIELD.to_string()])
+                .with_limit(1);
+            Ok(with_retry!(&cfg, backend.query(params.clone()))?.is_empty())
+        }).await
+    }
+
+    /// Get all objects from a collection in a vector
+    /// This is the typical manner in which you would iterate over all of the objects in the same collection as this one
+    /// With `default_query_limit` set in the config, at most that many objects are returned
+    async fn get() ->  Result<Vec<Self>, CloudSyncError> {
+        Self::collection_handle().get().await
+    }
+
+    /// Get at most `max` objects from the collection, as a safety cap on reading a collection that may have grown unexpectedly
+    async fn get_limit(max: u32) -> Result<Vec<Self>, CloudSyncError> {
+        Self::query().limit(max).run("get_limit").await
     }
-}
\ No newline at end of file
+
+    /// Get all objects from the collection except those marked deleted by `soft_rm()`
+    /// Firestore can't match documents that lack a field, so the marked documents are filtered out after they're read
+    async fn get_active() -> Result<Vec<Self>, CloudSyncError> {
+        trace::traced("get_active", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let docs = with_retry!(&cfg, backend.query(read_params(&cfg, &parent)))?;
+            let is_active = |doc: &&Document| match doc.fields.get(DELETED_AT_FIELD).and_then(|value| value.value_type.as_ref()) {
+                None | Some(value::ValueType::NullValue(_)) => true,
+                Some(_) => false,
+            };
+            let active: Vec<Document> = docs.iter().filter(is_active).cloned().collect();
+            deserialize_docs(&cfg, active).await
+        }).await
+    }
+
+    /// Get all objects from the collection, setting aside documents that don't deserialize into `Self` instead of failing the whole read
+    /// Returns the objects that could be read, alongside the id and error of every document that couldn't (e.g. during a schema migration)
+    async fn get_lenient() -> Result<(Vec<Self>, Vec<DeserializeFailure>), CloudSyncError> {
+        trace::traced("get_lenient", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let docs = with_retry!(&cfg, backend.query(read_params(&cfg, &parent)))?;
+            let mut objects = vec![];
+            let mut failures = vec![];
+            for doc in &docs {
+                match deserialize_doc(doc) {
+                    Ok(obj) => objects.push(obj),
+                    Err(err) => failures.push(DeserializeFailure { id: doc_id_of(&doc.name).to_string(), error: err.into() }),
+                }
+            }
+            Ok((objects, failures))
+        }).await
+    }
+
+    /// Get every object in the collection like `get()`, but deserialized into another type `R`, e.g. a slimmer read model
+    /// over the same documents than the type written to them. Fields of the stored documents that `R` doesn't have are ignored,
+    /// though they're still downloaded; use `get_projected()` to leave them out of the response too
+    async fn get_as<R>() -> Result<Vec<R>, CloudSyncError> where R: for<'a> Deserialize<'a> + Send + 'static {
+        trace::traced("get_as", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let docs = with_retry!(&cfg, backend.query(read_params(&cfg, &parent)))?;
+            deserialize_docs(&cfg, docs).await
+        }).await
+    }
+
+    /// Get only the named fields of every document in the collection, deserialized into a lighter struct `P`
+    /// `P`'s fields must be a subset of the stored document's fields, since everything else is left out of the response
+    async fn get_projected<P>(fields: &[&str]) -> Result<Vec<P>, CloudSyncError> where P: for<'a> Deserialize<'a> + Send {
+        trace::traced("get_projected", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_return_only_fields(fields.iter().map(|field| field.to_string()).collect());
+            let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+            from_docs(&docs)
+        }).await
+    }
+
+    /// Read a single field of the object with this uuid, without downloading or deserializing the rest of it,
+    /// e.g. `let status: Option<String> = User::get_field(&id, "status").await?`
+    /// Nested fields can be reached with dot notation. Returns `None` if there's no such object or it lacks the field
+    async fn get_field<V>(id: &T, field: &str) -> Result<Option<V>, CloudSyncError> where V: for<'a> Deserialize<'a> + Send {
+        trace::traced("get_field", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            #[derive(Deserialize)]
+            struct Field<V> {
+                value: V,
+            }
+
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let id = Self::doc_id_for(id);
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_filter(FirestoreQueryFilter::Compare(Some(FirestoreQueryFilterCompare::Equal(
+                    DOC_ID_FIELD.to_string(),
+                    doc_ref(&parent, &cfg.collection_id(), &id)?,
+                ))))
+                .with_return_only_fields(vec![field.to_string()])
+                .with_limit(1);
+            let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+            let Some((name, value)) = docs.into_iter().next().and_then(|doc| Some((doc.name.clone(), memory::field_value(&doc, field)?))) else {
+                return Ok(None);
+            };
+            let doc = Document { name, fields: HashMap::from([("value".to_string(), value)]), ..Default::default() };
+            Ok(Some(deserialize_doc::<Field<V>>(&doc)?.value))
+        }).await
+    }
+
+    /// Get only the named fields of every document in the collection as raw JSON objects, skipping deserialization into any struct
+    /// Useful when the fields to read are only known at runtime; `get_projected()` is the typed version
+    async fn get_raw(fields: &[&str]) -> Result<Vec<serde_json::Value>, CloudSyncError> {
+        trace::traced("get_raw", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_return_only_fields(fields.iter().map(|field| field.to_string()).collect());
+            let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+            from_docs(&docs)
+        }).await
+    }
+
+    /// Get the objects in every collection named like this one, at any depth in the database, as a firestore collection group query
+    /// The config's `parent_path` is ignored, so a type whose config names `orders` under `users/alice` gets the `orders` of every user
+    /// Security rules have to allow it with a recursive wildcard (`match /{path=**}/orders/{id}`). Reading a whole group needs no
+    /// extra index, but filtering or ordering one does need a collection group scoped index, which firestore reports as `MissingIndex`
+    async fn get_group() -> Result<Vec<Self>, CloudSyncError> {
+        trace::traced("get_group", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            // The client only marks a group's collections as reaching every descendant when asked to
+            let params = FirestoreQueryParams::new(FirestoreQueryCollection::Group(vec![cfg.collection_id()]))
+                .with_parent(backend.documents_path().to_string())
+                .with_all_descendants(true);
+            let docs = with_retry!(&cfg, backend.query(params.clone())).map_err(missing_index)?;
+            deserialize_docs(&cfg, docs).await
+        }).await
+    }
+
+    /// Get all objects from the collection as a stream, so they can be processed one at a time with bounded memory
+    /// A document that fails to deserialize shows up as an `Err` item without ending the stream
+    async fn get_stream() -> Result<BoxStream<'static, Result<Self, CloudSyncError>>, CloudSyncError> where Self: 'static {
+        trace::traced("get_stream", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let docs = with_retry!(&cfg, backend.query_stream(query_params(&parent, &cfg.collection_id())))?;
+            Ok(docs.map(|doc| Ok(deserialize_doc(&doc?)?)).boxed())
+        }).await
+    }
+
+    /// Get all objects whose `field` equals `value`, filtered server side
+    /// Nested fields can be reached with dot notation (e.g. `"address.city"`), and a value that serializes to null matches fields stored as null
+    async fn get_where(field: &str, value: impl Serialize + Send) -> Result<Vec<Self>, CloudSyncError> {
+        Self::query().filter(field, FilterOp::Eq, value).run("get_where").await
+    }
+
+    /// Get all objects matching every one of `filters`, combined server side with AND
+    /// Firestore needs a composite index for most queries filtering on several fields; when one is missing
+    /// this fails with `MissingIndex`, holding the console link to create it
+    /// Range filters (and `NotEq` / `NotIn`) on more than one field fail with `InvalidQuery` before anything is sent
+    async fn get_where_all(filters: &[Filter]) -> Result<Vec<Self>, CloudSyncError> {
+        filters.iter().cloned().fold(Self::query(), Query::with_filter).run("get_where_all").await
+    }
+
+    /// Get all objects whose `field` is between `min` and `max` (both included), ordered by that field
+    /// Documents missing the field, or holding a value of another type than the bounds, never match
+    async fn get_where_range(field: &str, min: impl Serialize + Send, max: impl Serialize + Send) -> Result<Vec<Self>, CloudSyncError> {
+        Self::query().filter(field, FilterOp::Gte, min).filter(field, FilterOp::Lte, max).run("get_where_range").await
+    }
+
+    /// Get all objects whose `field` is greater than `value`, ordered by that field
+    async fn get_where_gt(field: &str, value: impl Serialize + Send) -> Result<Vec<Self>, CloudSyncError> {
+        Self::query().filter(field, FilterOp::Gt, value).run("get_where_gt").await
+    }
+
+    /// Get all objects whose `field` is less than `value`, ordered by that field
+    async fn get_where_lt(field: &str, value: impl Serialize + Send) -> Result<Vec<Self>, CloudSyncError> {
+        Self::query().filter(field, FilterOp::Lt, value).run("get_where_lt").await
+    }
+
+    /// Get one object whose `field` equals `value`, or `None` if there is none, reading no more than that single document
+    /// Meant for lookups by a field that's expected to be unique, like an email
+    async fn first_where(field: &str, value: impl Serialize + Send) -> Result<Option<Self>, CloudSyncError> {
+        Ok(Self::query().filter(field, FilterOp::Eq, value).limit(1).run("first_where").await?.into_iter().next())
+    }
+
+    /// Get all objects whose `field` equals any one of `values`, filtered server side with firestore's `in` operator
+    /// Firestore caps how many values one `in` clause may hold, so longer lists are split over several queries and merged without duplicates
+    async fn get_where_in(field: &str, values: &[impl Serialize + Sync]) -> Result<Vec<Self>, CloudSyncError> {
+        trace::traced("get_where_in", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let values = to_values(values)?;
+            let mut seen = HashSet::new();
+            let mut objects = vec![];
+            for chunk in values.chunks(MAX_IN_VALUES) {
+                let list = FirestoreValue::from(Value { value_type: Some(value::ValueType::ArrayValue(ArrayValue { values: chunk.to_vec() })) });
+                let filter = Filter { field: field.to_string(), op: FilterOp::In, value: Some(list) };
+                let found = Self::query().with_filter(filter).execute().await?;
+                objects.extend(found.into_iter().filter(|obj| seen.insert(obj.uuid())));
+            }
+            Ok(objects)
+        }).await
+    }
+
+    /// Get all objects whose timestamp `field` is later than `since`, oldest first, for pulling only what changed since the last sync
+    /// The field has to be stored as a firestore timestamp (`#[serde(with = "firestore::serialize_as_timestamp")]`),
+    /// and pairs well with `Timestamped` and `save_timestamped()`, which keep an updated time current on every save
+    async fn get_modified_since(field: &str, since: DateTime<Utc>) -> Result<Vec<Self>, CloudSyncError> {
+        let since = FirestoreValue::from(Value { value_type: Some(value::ValueType::TimestampValue(to_timestamp(since))) });
+        let filter = Filter { field: field.to_string(), op: FilterOp::Gt, value: Some(since) };
+        Self::query().with_filter(filter).order_by(field, Order::Asc).run("get_modified_since").await
+    }
+
+    /// Get all objects sorted server side on `field` (or on the document id with `DOC_ID_FIELD`)
+    /// Firestore may reject the query, e.g. if it needs an index for this field, which comes back as an error
+    async fn get_ordered(field: &str, direction: Order) -> Result<Vec<Self>, CloudSyncError> {
+        Self::query().order_by(field, direction).run("get_ordered").await
+    }
+
+    /// Get up to `limit` objects ordered by uuid, starting just after the `cursor` uuid (or at the start when `None`)
+    /// Also returns the cursor to pass in for the next page, which is `None` once the collection is exhausted
+    async fn get_page(limit: u32, cursor: Option<T>) -> Result<(Vec<Self>, Option<T>), CloudSyncError> where T: 'async_trait {
+        trace::traced("get_page", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cursor = cursor.map(|cursor| Self::doc_id_for(&cursor));
+            let objects: Vec<Self> = read_page::<_, B>(&Self::config(), limit, cursor.as_deref()).await?;
+            let next = match objects.last() {
+                Some(last) if objects.len() as u32 == limit => Some(last.uuid()),
+                _ => None,
+            };
+            Ok((objects, next))
+        }).await
+    }
+
+    /// Get up to `limit` objects ordered by uuid that come just before the `cursor` uuid, for stepping back a page
+    /// Pass the first uuid of the current page to get the page before it. Firestore is read descending from the cursor,
+    /// but the objects come back in the same ascending order as `get_page()`
+    /// Also returns the cursor to pass in for the page before that, which is `None` once the start of the collection is reached
+    async fn get_page_before(limit: u32, cursor: T) -> Result<(Vec<Self>, Option<T>), CloudSyncError> where T: 'async_trait {
+        trace::traced("get_page_before", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_order_by(vec![FirestoreQueryOrder::new(DOC_ID_FIELD.to_string(), FirestoreQueryDirection::Descending)])
+                .with_start_at(FirestoreQueryCursor::AfterValue(vec![doc_ref(&parent, &cfg.collection_id(), &Self::doc_id_for(&cursor))?]))
+                .with_limit(limit);
+            let mut objects: Vec<Self> = from_docs(&with_retry!(&cfg, backend.query(params.clone()))?)?;
+            objects.reverse();
+            let prev = match objects.first() {
+                Some(first) if objects.len() as u32 == limit => Some(first.uuid()),
+                _ => None,
+            };
+            Ok((objects, prev))
+        }).await
+    }
+
+    /// Get every object whose document id is at least `start` and less than `end`, ordered by id
+    /// `start` is included and `end` isn't, so adjoining ranges like `("", "m")` and `("m", "")` split a collection without overlap;
+    /// an empty `start` or `end` leaves that side of the range open. Ids compare as firestore orders them, which is
+    /// byte-wise for string ids. Each range is a plain indexed scan, which makes it the building block for reading
+    /// a large collection in partitions, e.g. in parallel. `default_query_limit` doesn't apply
+    async fn get_id_range(start: &str, end: &str) -> Result<Vec<Self>, CloudSyncError> {
+        trace::traced("get_id_range", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = id_range_params(&parent, &cfg.collection_id(), start, end)?;
+            let docs = with_retry!(&cfg, backend.query(params.clone()))?;
+            deserialize_docs(&cfg, docs).await
+        }).await
+    }
+
+    /// Pass every object in the collection through `f`, scanning `shards` id ranges (see `get_id_range()`) with up to `concurrency` of them at once
+    /// Objects within a shard are streamed and handled one after another, so memory stays bounded by the shards in flight
+    /// The split points are spread evenly over the characters of firestore's auto-ids, so shards come out balanced for auto-id
+    /// collections and lopsided for ids that cluster (the outer shards are open-ended, so nothing is ever missed)
+    /// Stops at the first error from a read or from `f`; objects in other shards may have been handled by then
+    async fn scan_parallel<F, Fut>(shards: usize, concurrency: usize, f: F) -> Result<(), CloudSyncError>
+    where
+        F: Fn(Self) -> Fut + Send + Sync + 'async_trait,
+        Fut: std::future::Future<Output = Result<(), CloudSyncError>> + Send,
+    {
+        trace::traced("scan_parallel", &Self::config().collection_id(), None, Self::config().operation_timeout, async move {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let (cfg, backend, parent, f) = (&cfg, &backend, &parent, &f);
+            let bounds = shard_bounds(shards);
+            let starts = std::iter::once(String::new()).chain(bounds.clone());
+            let ends = bounds.into_iter().chain(std::iter::once(String::new()));
+            let scans = futures::stream::iter(starts.zip(ends))
+                .map(|(start, end)| async move {
+                    let params = id_range_params(parent, &cfg.collection_id(), &start, &end)?;
+                    let mut docs = with_retry!(cfg, backend.query_stream(params.clone()))?;
+                    while let Some(doc) = docs.next().await {
+                        f(deserialize_doc(&doc?)?).await?;
+                    }
+                    Ok::<_, CloudSyncError>(())
+                })
+                .buffer_unordered(concurrency.max(1));
+            let mut scans = std::pin::pin!(scans);
+            while let Some(result) = scans.next().await {
+                result?;
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Get an arbitrary object from the collection, or `None` if it's empty, reading a single document
+    /// This picks a random document id and takes the first document at or after it, wrapping around to the start if there is none
+    /// Each object's chance of being picked is proportional to the gap between its id and the one before it,
+    /// so the sample is only close to uniform when ids are spread evenly, like firestore's random auto-ids
+    async fn get_random() -> Result<Option<Self>, CloudSyncError> {
+        trace::traced("get_random", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let params = query_params(&parent, &cfg.collection_id())
+                .with_order_by(vec![FirestoreQueryOrder::new(DOC_ID_FIELD.to_string(), FirestoreQueryDirection::Ascending)])
+                .with_limit(1);
+            let random_id = rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(20).map(char::from).collect::<String>();
+            let after = params.clone().with_start_at(FirestoreQueryCursor::BeforeValue(vec![doc_ref(&parent, &cfg.collection_id(), &random_id)?]));
+            let mut docs = with_retry!(&cfg, backend.query(after.clone()))?;
+            if docs.is_empty() {
+                docs = with_retry!(&cfg, backend.query(params.clone()))?;
+            }
+            Ok(from_docs(&docs)?.into_iter().next())
+        }).await
+    }
+
+    /// Get all objects from the collection by reading it `page_size` objects at a time with `get_page()`
+    /// This bounds how much each request returns, but still buffers the whole collection; use `get_stream()` to avoid that
+    async fn get_all_paged(page_size: u32) -> Result<Vec<Self>, CloudSyncError> {
+        trace::traced("get_all_paged", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let (cfg, page_size) = (Self::config(), page_size.max(1));
+            let mut objects = vec![];
+            let mut cursor = None;
+            loop {
+                let page: Vec<Self> = read_page::<_, B>(&cfg, page_size, cursor.as_deref()).await?;
+                let full = page.len() as u32 == page_size;
+                cursor = page.last().map(|last| last.doc_id());
+                objects.extend(page);
+                if !full {
+                    return Ok(objects);
+                }
+            }
+        }).await
+    }
+
+    /// Get all items from the collection this object is in as a HashMap
+    /// This is the typical manner in which you would find a specific object
+    async fn hash() -> Result<HashMap<T, Self>, CloudSyncError> {
+        trace::traced("hash", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let objects: Vec<Self> = read_all(&B::connect(&cfg).await?, &cfg).await?;
+            let mut hash = HashMap::with_capacity(objects.len());
+            hash.extend(objects.into_iter().map(|obj| (obj.uuid(), obj)));
+            Ok(hash)
+        }).await
+    }
+
+    /// Get all objects like `get()`, each wrapped in an `Arc` as it's deserialized, ready to share from a cache without cloning
+    async fn get_arc() -> Result<Vec<Arc<Self>>, CloudSyncError> {
+        trace::traced("get_arc", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let docs = with_retry!(&cfg, backend.query(read_params(&cfg, &parent)))?;
+            Ok(docs.iter().map(|doc| deserialize_doc(doc).map(Arc::new)).collect::<Result<_, _>>()?)
+        }).await
+    }
+
+    /// Get all items from the collection like `hash()`, each wrapped in an `Arc` as it's deserialized straight into the map
+    async fn hash_arc() -> Result<HashMap<T, Arc<Self>>, CloudSyncError> {
+        trace::traced("hash_arc", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let docs = with_retry!(&cfg, backend.query(read_params(&cfg, &parent)))?;
+            let mut hash = HashMap::with_capacity(docs.len());
+            for doc in &docs {
+                let obj: Self = deserialize_doc(doc)?;
+                hash.insert(obj.uuid(), Arc::new(obj));
+            }
+            Ok(hash)
+        }).await
+    }
+
+    /// Get all items from the collection in a HashMap keyed by `key_fn` instead of the uuid, e.g. on another unique field like an email
+    /// When two objects map to the same key, only one of them is kept
+    async fn hash_by<K, F>(key_fn: F) -> Result<HashMap<K, Self>, CloudSyncError>
+    where
+        K: std::cmp::Eq + std::hash::Hash,
+        F: Fn(&Self) -> K + Send + 'async_trait,
+    {
+        trace::traced("hash_by", &Self::config().collection_id(), None, Self::config().operation_timeout, async move {
+            let cfg = Self::config();
+            let objects: Vec<Self> = read_all(&B::connect(&cfg).await?, &cfg).await?;
+            Ok(objects.into_iter().map(|obj| (key_fn(&obj), obj)).collect())
+        }).await
+    }
+
+    /// Like `hash()`, but fails with `DuplicateUuid` naming the uuid instead of silently keeping only one object
+    /// if two documents in the collection map to the same uuid
+    async fn hash_strict() -> Result<HashMap<T, Self>, CloudSyncError> {
+        trace::traced("hash_strict", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let objects: Vec<Self> = read_all(&B::connect(&cfg).await?, &cfg).await?;
+            let mut hash = HashMap::with_capacity(objects.len());
+            for obj in objects {
+                let id = obj.uuid();
+                if hash.contains_key(&id) {
+                    return Err(CloudSyncError::DuplicateUuid(id.to_doc_id()));
+                }
+                hash.insert(id, obj);
+            }
+            Ok(hash)
+        }).await
+    }
+
+    /// Get a single object from the collection by its uuid
+    /// Returns `None` if no document with that uuid exists, without fetching the rest of the collection
+    async fn get_by_id(id: &T) -> Result<Option<Self>, CloudSyncError> {
+        Self::collection_handle().get_by_id(id).await
+    }
+
+    /// Get the objects with these uuids in a single batched read
+    /// Uuids with no saved object are simply absent from the result, and the order of the result isn't guaranteed to match `ids`
+    async fn get_many_by_ids(ids: &[T]) -> Result<Vec<Self>, CloudSyncError> {
+        trace::traced("get_many_by_ids", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            if ids.is_empty() {
+                return Ok(vec![]);
+            }
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let ids: Vec<String> = ids.iter().map(Self::doc_id_for).collect();
+            ids.iter().try_for_each(|id| check_doc_id(id))?;
+            from_docs(&with_retry!(&cfg, backend.get_docs(&parent, &cfg.collection_id(), &ids))?)
+        }).await
+    }
+
+    /// Get a single object by its uuid along with the time its document was last updated, for use with `save_checked()`
+    async fn get_with_update_time(id: &T) -> Result<Option<(Self, DateTime<Utc>)>, CloudSyncError> {
+        trace::traced("get_with_update_time", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            let found: Option<(Self, DocMeta)> = read_with_meta::<_, B>(&Self::config(), &Self::doc_id_for(id)).await?;
+            Ok(found.map(|(obj, meta)| (obj, meta.update_time)))
+        }).await
+    }
+
+    /// Get a single object by its uuid along with its document's metadata: its full path and when it was created and last updated
+    async fn get_with_meta(id: &T) -> Result<Option<(Self, DocMeta)>, CloudSyncError> {
+        trace::traced("get_with_meta", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            read_with_meta::<_, B>(&Self::config(), &Self::doc_id_for(id)).await
+        }).await
+    }
+
+    /// Check whether an object with this uuid is saved, without downloading any of its fields
+    async fn exists(id: &T) -> Result<bool, CloudSyncError> {
+        Self::collection_handle().exists(id).await
+    }
+
+    /// Count the objects in the collection without downloading any of them
+    /// This uses firestore's aggregation count query, so only the number comes back over the wire
+    async fn count() -> Result<usize, CloudSyncError> {
+        Self::collection_handle().count().await
+    }
+
+    /// Atomically add `delta` (which may be negative) to a numeric field of the object with this uuid, without reading it first
+    /// A missing document or field counts as zero, and a field holding anything other than a number is rejected with `WrongFieldType`
+    async fn increment(id: &T, field: &str, delta: i64) -> Result<(), CloudSyncError> {
+        trace::traced("increment", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            let cfg = Self::config();
+            let backend = B::connect(&cfg).await?;
+            let parent = cfg.parent(backend.documents_path());
+            let id = Self::doc_id_for(id);
+            let is_number = |value: &value::ValueType| matches!(value, value::ValueType::IntegerValue(_) | value::ValueType::DoubleValue(_));
+            check_field_type(&backend, &cfg, &id, field, "a number", is_number).await?;
+            let increment = FieldTransform {
+                field_path: field.to_string(),
+                transform_type: Some(field_transform::TransformType::Increment(Value { value_type: Some(value::ValueType::IntegerValue(delta)) })),
+            };
+            let write = transform_write(&parent, &cfg.collection_id(), &id, vec![increment])?;
+            with_retry!(&cfg, backend.commit(vec![write.clone()]))
+        }).await
+    }
+
+    /// Atomically add each of `values` that isn't already there to the array `field` of the object with this uuid
+    /// Unlike saving the whole object, concurrent adds (and removes) to the same array never overwrite each other;
+    /// a missing document or field starts out as an empty array, and a field holding anything else is rejected with `WrongFieldType`
+    ///
+    /// The add is sent as a firestore `arrayUnion` transform, which firestore applies to the stored array itself,
+    /// rather than being read, modified and written back by the client, so one client's add can't drop another's.
+    /// An add and a remove of the same value racing each other depend on which firestore applies last
+    async fn array_add(id: &T, field: &str, values: &[impl Serialize + Sync]) -> Result<(), CloudSyncError> {
+        trace::traced("array_add", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            commit_array_transform::<B>(&Self::config(), &Self::doc_id_for(id), field, to_values(values)?, false).await
+        }).await
+    }
+
+    /// Atomically remove every element equal to one of `values` from the array `field` of the object with this uuid
+    async fn array_remove(id: &T, field: &str, values: &[impl Serialize + Sync]) -> Result<(), CloudSyncError> {
+        trace::traced("array_remove", &Self::config().collection_id(), Some(&Self::doc_id_for(id)), Self::config().operation_timeout, async {
+            commit_array_transform::<B>(&Self::config(), &Self::doc_id_for(id), field, to_values(values)?, true).await
+        }).await
+    }
+
+    /// Listen for changes to the collection in real time, starting with an `Added` event for every object already in it
+    /// The snapshot and the live changes come from the same listener, so there's no need to `get()` the collection first
+    /// and risk missing (or doubling up on) a change made between the two
+    /// Transient disconnects are reconnected internally according to the config's `RetryPolicy`, so the stream only ends after
+    /// an `Err` item once the listener gives up; an object that fails to deserialize shows up as an `Err` item without ending it
+    async fn listen() -> Result<BoxStream<'static, Result<ChangeEvent<Self, T>, CloudSyncError>>, CloudSyncError> where Self: 'static, T: 'static {
+        trace::traced("listen", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            change_events::<Self, T, B>(&Self::config()).await
+        }).await
+    }
+
+    /// Watch the collection: every object already in it as an `Added` event first, then each live change as it happens
+    /// This is the "query snapshot then subscribe" pattern in a single stream, saving a reactive client from racing an initial
+    /// `get()` against a separate listener. It yields the same events as `listen()`, reconnecting in the same way
+    async fn watch() -> Result<BoxStream<'static, Result<ChangeEvent<Self, T>, CloudSyncError>>, CloudSyncError> where Self: 'static, T: 'static {
+        trace::traced("watch", &Self::config().collection_id(), None, Self::config().operation_timeout, async {
+            change_events::<Self, T, B>(&Self::config()).await
+        }).await
+    }
+
+    // TODO
+    // async fn this()
+
+    /// Get this objects cloud config, not intended for use outside of the crate
+    fn config() -> CLConfig;
+
+    /// A handle on this type's collection, to pass the collection around as a value (see `Collection`)
+    fn collection_handle() -> Collection<Self, T, B> {
+        Collection::new(Self::config())
+    }
+
+    /// A query over this type's collection, to combine filters, ordering, a limit and a cursor freely (see `Query`)
+    fn query() -> Query<Self, T, B> {
+        Query::new(Self::config())
+    }
+
+    /// The collection this particular object is saved to and removed from, defaulting to the config's collection (after its `collection_prefix`)
+    /// Override this to shard objects across collections (e.g. per tenant), static methods like `get()` still read the config's collection.
+    /// An override names the full collection itself, so the config's `collection_prefix` isn't added to it
+    fn collection(&self) -> String {
+        Self::config().collection_id()
+    }
+}
+
+/// Firestore's metadata about a stored document, as returned by `CloudSync::get_with_meta()`
+///
+/// # Fields:
+/// - path: the full name of the document, e.g. `projects/{project_id}/databases/(default)/documents/users/alice`
+/// - id: the document's id, the last segment of its path
+/// - create_time: when the document was first written
+/// - update_time: when the document was last written, which `save_checked()` can compare against
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocMeta {
+    pub path: String,
+    pub id: String,
+    pub create_time: DateTime<Utc>,
+    pub update_time: DateTime<Utc>,
+}
+
+/// A document `CloudSync::get_lenient()` couldn't deserialize
+///
+/// # Fields:
+/// - id: the id of the document
+/// - error: why it couldn't be deserialized
+#[derive(Debug)]
+pub struct DeserializeFailure {
+    pub id: String,
+    pub error: CloudSyncError,
+}
+
+/// What `CloudSync::sync()` did to the collection
+///
+/// # Fields:
+/// - created: objects that weren't stored yet and were written
+/// - updated: objects whose stored document differed and was overwritten
+/// - deleted: stored documents that weren't among the objects and were removed
+/// - unchanged: objects already stored exactly as they are, which weren't written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub unchanged: usize,
+}
+
+/// A change to the collection seen by `CloudSync::listen()` or `CloudSync::watch()`
+///
+/// # Variants:
+/// - Added: an object was saved that wasn't in the collection before (including every object there when listening starts)
+/// - Modified: an object already in the collection was saved again, with its new contents
+/// - Removed: the object with this uuid was removed from the collection
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChangeEvent<S, T> {
+    Added(S),
+    Modified(S),
+    Removed(T),
+}
+
+/// The direction query results are sorted in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+impl From<Order> for FirestoreQueryDirection {
+    fn from(order: Order) -> Self {
+        match order {
+            Order::Asc => FirestoreQueryDirection::Ascending,
+            Order::Desc => FirestoreQueryDirection::Descending,
+        }
+    }
+}
+
+/// List the ids of the collections in the database `cfg` points at (or, with a `parent_path`, of those under that document),
+/// for admin tooling; `cfg.collection` isn't used. Only a collection holding at least one document is listed
+/// e.g. `cloudsync::list_collection_ids::<FirestoreBackend>(&cfg)`
+pub async fn list_collection_ids<B: Backend>(cfg: &CLConfig) -> Result<Vec<String>, CloudSyncError> {
+    trace::traced("list_collection_ids", &cfg.collection_id(), None, cfg.operation_timeout, async {
+        let backend = B::connect(cfg).await?;
+        let parent = cfg.parent(backend.documents_path());
+        with_retry!(&cfg, backend.list_collection_ids(&parent))
+    }).await
+}
+
+/// How a field is compared to a value when filtering, mirroring firestore's query operators
+/// `In`, `NotIn` and `ArrayContainsAny` compare against each element of a value that serializes to a list
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    NotEq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    ArrayContains,
+    ArrayContainsAny,
+    In,
+    NotIn,
+}
+
+impl FilterOp {
+    /// Whether this is one of the operators firestore only allows on a single field per query
+    fn is_range(self) -> bool {
+        matches!(self, FilterOp::Lt | FilterOp::Lte | FilterOp::Gt | FilterOp::Gte | FilterOp::NotEq | FilterOp::NotIn)
+    }
+
+    /// The firestore filter comparing `field` to `value`, where `None` stands for null
+    /// Firestore only matches null with `IS NULL` / `IS NOT NULL`, so that's what `Eq` and `NotEq` turn into for it
+    fn filter(self, field: &str, value: Option<FirestoreValue>) -> FirestoreQueryFilter {
+        let field = field.to_string();
+        let value = match (self, value) {
+            (FilterOp::Eq, None) => return FirestoreQueryFilter::Unary(FirestoreQueryFilterUnary::IsNull(field)),
+            (FilterOp::NotEq, None) => return FirestoreQueryFilter::Unary(FirestoreQueryFilterUnary::IsNotNull(field)),
+            (_, Some(value)) => value,
+            (_, None) => FirestoreValue::from(Value { value_type: Some(value::ValueType::NullValue(0)) }),
+        };
+        FirestoreQueryFilter::Compare(Some(match self {
+            FilterOp::Eq => FirestoreQueryFilterCompare::Equal(field, value),
+            FilterOp::NotEq => FirestoreQueryFilterCompare::NotEqual(field, value),
+            FilterOp::Lt => FirestoreQueryFilterCompare::LessThan(field, value),
+            FilterOp::Lte => FirestoreQueryFilterCompare::LessThanOrEqual(field, value),
+            FilterOp::Gt => FirestoreQueryFilterCompare::GreaterThan(field, value),
+            FilterOp::Gte => FirestoreQueryFilterCompare::GreaterThanOrEqual(field, value),
+            FilterOp::ArrayContains => FirestoreQueryFilterCompare::ArrayContains(field, value),
+            FilterOp::ArrayContainsAny => FirestoreQueryFilterCompare::ArrayContainsAny(field, value),
+            FilterOp::In => FirestoreQueryFilterCompare::In(field, value),
+            FilterOp::NotIn => FirestoreQueryFilterCompare::NotIn(field, value),
+        }))
+    }
+}
+
+/// One condition of a `CloudSync::get_where_all()` query: `field` compared to `value` with `op`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    field: String,
+    op: FilterOp,
+    value: Option<FirestoreValue>,
+}
+
+impl Filter {
+    /// Build a condition, failing if `value` can't be serialized
+    /// Nested fields can be reached with dot notation (e.g. `"address.city"`)
+    pub fn new(field: impl Into<String>, op: FilterOp, value: impl Serialize) -> Result<Filter, CloudSyncError> {
+        Ok(Filter { field: field.into(), op, value: query_value(&value)? })
+    }
+}
+
+/// Each object implementing this trait can provide a uuid for itself
+pub trait Unique<T> where T: Serialize {
+
+    /// Get the uuid of this object
+    fn uuid(&self) -> T;
+
+    /// Get the id of the document the object with this uuid is stored under, which is the uuid's `to_doc_id()` unless overridden
+    /// Override this to e.g. prefix or hash ids; saves and lookups by uuid like `get_by_id()` all go through it
+    fn doc_id_for(uuid: &T) -> String where T: ToDocId, Self: Sized {
+        uuid.to_doc_id()
+    }
+
+    /// Get the id of the document this object is stored under
+    fn doc_id(&self) -> String where T: ToDocId, Self: Sized {
+        Self::doc_id_for(&self.uuid())
+    }
+}
+
+/// Turns a uuid into the id of the firestore document its object is stored under
+/// Anything `Display` already is one; implement it by hand for uuids like composite keys that have no natural string form
+/// The id has to be one firestore allows (non-empty, no `/`, not `.` or `..`), or saving, getting and removing by it fail with `InvalidDocumentId`
+pub trait ToDocId {
+
+    /// Get the document id for this uuid
+    fn to_doc_id(&self) -> String;
+}
+
+impl<T> ToDocId for T where T: std::fmt::Display + ?Sized {
+    fn to_doc_id(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Objects implementing this trait can be saved with `save_timestamped()`, which keeps their write times up to date
+pub trait Timestamped {
+
+    /// Set the time this object was first saved
+    fn set_created(&mut self, t: DateTime<Utc>);
+
+    /// Set the time this object was last saved
+    fn set_updated(&mut self, t: DateTime<Utc>);
+}
+
+/// Objects keyed by a `uuid::Uuid` implementing this trait can be saved with `save_with_new_uuid()`, which fills in a nil uuid
+/// The uuid is stored under its hyphenated lowercase form, which `Uuid::parse_str()` reads back unchanged
+/// Only available with the `uuid` feature
+#[cfg(feature = "uuid")]
+pub trait AssignUuid: Unique<uuid::Uuid> {
+
+    /// Set this object's uuid
+    fn set_uuid(&mut self, uuid: uuid::Uuid);
+}
+/// Where the credentials for a project come from
+///
+/// # Variants:
+/// - File: the path to the credentials json file downloaded from firebase
+/// - Json: the contents of that file, e.g. injected through a secret environment variable
+/// - Adc: google's application default credentials, as found by the gcloud sdk
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CredentialSource {
+    File(PathBuf),
+    Json(String),
+    #[default]
+    Adc,
+}
+
+/// The config for how this object syncs with the cloud
+/// 
+/// # Fields:
+/// - project_id: name of the the project in firebase
+/// - credentials: where to find the credentials json downloaded from firebase (see `CredentialSource`)
+/// - collection: the name of the collection that objects of this type should be saved to (override `CloudSync::collection()` to send individual objects elsewhere)
+/// - parent_path: the document the collection is nested under, e.g. `Some("users/alice")` for `users/alice/orders`, or `None` for a top-level collection
+/// - retry: how transient firestore errors are retried (see `RetryPolicy`, the default retries a few times)
+/// - operation_timeout: how long a single operation (including its retries) may take before it fails with `Timeout`, or `None` to wait forever
+///   Streaming operations like `listen()` are only bounded while they start
+/// - database_id: the named firestore database to use within the project, or `None` for the `(default)` one
+/// - default_query_limit: the most objects `get()` (and so `hash()`), `get_active()`, `get_lenient()`, `get_as()` and queries
+///   without a `limit()` of their own return, guarding against runaway reads, or `None` for no limit
+/// - scopes: the oauth scopes requested for the credentials, e.g. just `https://www.googleapis.com/auth/datastore`, or `None` for google's default set
+/// - write_nulls: whether `None` fields are saved as explicit nulls (the default), so they match `field == null` queries,
+///   or left out of the document entirely to keep it lean. With `save_merge()` a left out field is also left out of the merge,
+///   so the stored value survives instead of being cleared
+/// - blocking_deserialize_threshold: reads returning at least this many documents (`get()`, `get_where()` and the like) deserialize them
+///   on a blocking thread instead of the async task, keeping the executor responsive during big pulls, or `None` to always deserialize in place.
+///   As a rough guide, handing the work over costs about as much as deserializing a few dozen small documents, so it pays off for reads in the thousands
+/// - dry_run: when on, `rm()`, `rm_counted()`, `rm_by_id()`, `rm_by_id_strict()`, `rm_batch()`, `rm_where()` and `clear_collection()`
+///   log each document they would delete (as `tracing` events) and return what they would have, without deleting anything.
+///   Everything else, including the deletes `sync()` makes, runs as usual
+/// - collection_prefix: put in front of `collection` by every operation, e.g. `Some("tenant42_")` to keep a tenant's objects in `tenant42_users`,
+///   or `None` to use the collection name as it is
+/// - max_document_bytes: the largest document a save may send, counted the way firestore counts its storage size. A bigger object fails
+///   with `DocumentTooLarge` before anything is sent. Defaults to firestore's own limit of 1 MiB, above which it rejects the write anyway
+/// - circuit_breaker: after this many transient failures in a row, fail every call to the same database with `CircuitOpen` for a cooldown
+///   instead of piling more retries onto an outage (see `CircuitBreakerPolicy`), or `None` (the default) to always call firestore
+/// - read_time: read documents, query results and counts as they were at this time rather than as they are now, so several reads
+///   through the same config (e.g. a `Collection` handle) all see one consistent snapshot while the collection keeps changing.
+///   Writes and `listen()` are unaffected. Firestore keeps versions for an hour, or for 7 days with point-in-time recovery
+///   enabled on the database (where times more than an hour old must fall on a whole minute); older times are rejected.
+///   The in-memory backend keeps no history and fails to connect with `Unsupported`. `None` (the default) reads the latest data
+///
+#[derive(Clone, Debug)]
+pub struct CLConfig {
+    pub project_id: String,
+    pub credentials: CredentialSource,
+    pub collection: String,
+    pub parent_path: Option<String>,
+    pub retry: RetryPolicy,
+    pub operation_timeout: Option<Duration>,
+    pub scopes: Option<Vec<String>>,
+    pub default_query_limit: Option<u32>,
+    pub database_id: Option<String>,
+    pub write_nulls: bool,
+    pub blocking_deserialize_threshold: Option<usize>,
+    pub dry_run: bool,
+    pub collection_prefix: Option<String>,
+    pub max_document_bytes: usize,
+    pub circuit_breaker: Option<CircuitBreakerPolicy>,
+    pub read_time: Option<DateTime<Utc>>,
+}
+
+impl Default for CLConfig {
+    fn default() -> Self {
+        CLConfig {
+            project_id: String::new(),
+            credentials: CredentialSource::default(),
+            collection: String::new(),
+            parent_path: None,
+            retry: RetryPolicy::default(),
+            operation_timeout: None,
+            scopes: None,
+            default_query_limit: None,
+            database_id: None,
+            write_nulls: true,
+            blocking_deserialize_threshold: None,
+            dry_run: false,
+            collection_prefix: None,
+            max_document_bytes: MAX_DOCUMENT_BYTES,
+            circuit_breaker: None,
+            read_time: None,
+        }
+    }
+}
+
+impl CLConfig {
+    /// The id of the collection as it's stored, which is `collection` with the `collection_prefix` in front
+    pub fn collection_id(&self) -> String {
+        match &self.collection_prefix {
+            Some(prefix) => format!("{}{}", prefix, self.collection),
+            None => self.collection.clone(),
+        }
+    }
+
+    /// The full path the collection sits under, given the backend's documents path
+    pub fn parent(&self, documents_path: &str) -> String {
+        match self.parent_path.as_deref().map(|path| path.trim_matches('/')) {
+            Some(path) if !path.is_empty() => format!("{}/{}", documents_path, path),
+            _ => documents_path.to_string(),
+        }
+    }
+
+    /// Build a config for `collection` from the environment
+    /// project_id comes from `GOOGLE_CLOUD_PROJECT` (or `CLOUDSYNC_PROJECT_ID`), and the credentials file from `GOOGLE_APPLICATION_CREDENTIALS`
+    pub fn from_env(collection: &str) -> Result<CLConfig, CloudSyncError> {
+        let project_id = std::env::var("GOOGLE_CLOUD_PROJECT")
+            .or_else(|_| std::env::var("CLOUDSYNC_PROJECT_ID"))
+            .map_err(|_| CloudSyncError::MissingEnvVar("GOOGLE_CLOUD_PROJECT or CLOUDSYNC_PROJECT_ID".to_string()))?;
+        let cred_path = std::env::var("GOOGLE_APPLICATION_CREDENTIALS")
+            .map_err(|_| CloudSyncError::MissingEnvVar("GOOGLE_APPLICATION_CREDENTIALS".to_string()))?;
+        Ok(CLConfig {
+            project_id,
+            credentials: CredentialSource::File(PathBuf::from(cred_path)),
+            collection: collection.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// A config for `collection` with the project and credentials from `set_global_config()`,
+    /// failing with `GlobalConfigNotSet` if that hasn't been called yet
+    /// e.g. `fn config() -> CLConfig { CLConfig::with_collection("users").expect("global config is set at startup") }`
+    pub fn with_collection(collection: &str) -> Result<CLConfig, CloudSyncError> {
+        let global = global_config().ok_or(CloudSyncError::GlobalConfigNotSet)?;
+        Ok(CLConfig {
+            project_id: global.project_id.clone(),
+            credentials: global.credentials.clone(),
+            collection: collection.to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// The config generated by `#[derive(CloudSync)]`, taking whatever wasn't given in the attribute from the global config
+    /// if it's set, and otherwise from the environment
+    #[doc(hidden)]
+    pub fn __derived(collection: &str, project_id: Option<&str>, cred_path: Option<&str>, parent_path: Option<&str>) -> CLConfig {
+        let global = global_config();
+        let project_id = match (project_id, global) {
+            (Some(project_id), _) => project_id.to_string(),
+            (None, Some(global)) => global.project_id.clone(),
+            (None, None) => std::env::var("GOOGLE_CLOUD_PROJECT").or_else(|_| std::env::var("CLOUDSYNC_PROJECT_ID")).unwrap_or_default(),
+        };
+        let credentials = match (cred_path, global) {
+            (Some(path), _) => CredentialSource::File(PathBuf::from(path)),
+            (None, Some(global)) => global.credentials.clone(),
+            (None, None) => match std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+                Ok(path) => CredentialSource::File(PathBuf::from(path)),
+                Err(_) => CredentialSource::Adc,
+            },
+        };
+        CLConfig {
+            project_id,
+            credentials,
+            collection: collection.to_string(),
+            parent_path: parent_path.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    /// Start building a config, which checks that every required field is set when it's built
+    pub fn builder() -> CLConfigBuilder {
+        CLConfigBuilder::default()
+    }
+}
+
+/// Builds a `CLConfig`, see `CLConfig::builder()`
+/// project_id, collection and the credentials are required, everything else falls back to the `CLConfig` defaults
+#[derive(Clone, Debug, Default)]
+pub struct CLConfigBuilder {
+    project_id: Option<String>,
+    credentials: Option<CredentialSource>,
+    collection: Option<String>,
+    parent_path: Option<String>,
+    retry: Option<RetryPolicy>,
+    operation_timeout: Option<Duration>,
+    scopes: Option<Vec<String>>,
+    default_query_limit: Option<u32>,
+    database_id: Option<String>,
+    write_nulls: Option<bool>,
+    blocking_deserialize_threshold: Option<usize>,
+    dry_run: bool,
+    collection_prefix: Option<String>,
+    max_document_bytes: Option<usize>,
+    circuit_breaker: Option<CircuitBreakerPolicy>,
+    read_time: Option<DateTime<Utc>>,
+}
+
+impl CLConfigBuilder {
+    /// Set the name of the project in firebase
+    pub fn project_id(mut self, project_id: impl Into<String>) -> Self {
+        self.project_id = Some(project_id.into());
+        self
+    }
+
+    /// Read the credentials from the json file at this path
+    pub fn cred_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.credentials = Some(CredentialSource::File(path.into()));
+        self
+    }
+
+    /// Set where the credentials come from, for sources other than a file
+    pub fn credentials(mut self, credentials: CredentialSource) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    /// Set the collection objects are saved to
+    pub fn collection(mut self, collection: impl Into<String>) -> Self {
+        self.collection = Some(collection.into());
+        self
+    }
+
+    /// Nest the collection under this document
+    pub fn parent_path(mut self, parent_path: impl Into<String>) -> Self {
+        self.parent_path = Some(parent_path.into());
+        self
+    }
+
+    /// Set how transient errors are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Set how long a single operation may take before it fails with `Timeout`
+    pub fn operation_timeout(mut self, timeout: Duration) -> Self {
+        self.operation_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the oauth scopes requested for the credentials instead of google's default set
+    pub fn scopes<S: Into<String>>(mut self, scopes: impl IntoIterator<Item = S>) -> Self {
+        self.scopes = Some(scopes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the most objects `get()` returns
+    pub fn default_query_limit(mut self, limit: u32) -> Self {
+        self.default_query_limit = Some(limit);
+        self
+    }
+
+    /// Set the named firestore database to use instead of the `(default)` one
+    pub fn database_id(mut self, database_id: impl Into<String>) -> Self {
+        self.database_id = Some(database_id.into());
+        self
+    }
+
+    /// Set whether `None` fields are saved as explicit nulls or left out of the document
+    pub fn write_nulls(mut self, write_nulls: bool) -> Self {
+        self.write_nulls = Some(write_nulls);
+        self
+    }
+
+    /// Deserialize reads of at least this many documents on a blocking thread
+    pub fn blocking_deserialize_threshold(mut self, threshold: usize) -> Self {
+        self.blocking_deserialize_threshold = Some(threshold);
+        self
+    }
+
+    /// Set whether deletes are only logged and counted instead of carried out
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Put this in front of the collection name for every operation, e.g. a tenant's id
+    pub fn collection_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.collection_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set the largest document, in firestore's own measure of bytes, that saving is allowed to send
+    pub fn max_document_bytes(mut self, bytes: usize) -> Self {
+        self.max_document_bytes = Some(bytes);
+        self
+    }
+
+    /// Stop calling firestore for a while after it keeps failing, see `CircuitBreakerPolicy`
+    pub fn circuit_breaker(mut self, policy: CircuitBreakerPolicy) -> Self {
+        self.circuit_breaker = Some(policy);
+        self
+    }
+
+    /// Read every collection as it was at this time, see the `read_time` field of `CLConfig`
+    pub fn read_time(mut self, read_time: DateTime<Utc>) -> Self {
+        self.read_time = Some(read_time);
+        self
+    }
+
+    /// Build the config, failing with `MissingConfig` naming every required field that wasn't set,
+    /// or with `CredentialsNotFound` if the credentials file doesn't exist
+    pub fn build(self) -> Result<CLConfig, CloudSyncError> {
+        let mut missing = vec![];
+        if self.project_id.as_deref().is_none_or(str::is_empty) {
+            missing.push("project_id".to_string());
+        }
+        if self.credentials.is_none() {
+            missing.push("cred_path".to_string());
+        }
+        if self.collection.as_deref().is_none_or(str::is_empty) {
+            missing.push("collection".to_string());
+        }
+        if !missing.is_empty() {
+            return Err(CloudSyncError::MissingConfig(missing));
+        }
+        let credentials = self.credentials.unwrap();
+        if let CredentialSource::File(path) = &credentials {
+            if !path.exists() {
+                return Err(CloudSyncError::CredentialsNotFound(path.clone()));
+            }
+        }
+        Ok(CLConfig {
+            project_id: self.project_id.unwrap(),
+            credentials,
+            collection: self.collection.unwrap(),
+            parent_path: self.parent_path,
+            retry: self.retry.unwrap_or_default(),
+            operation_timeout: self.operation_timeout,
+            scopes: self.scopes,
+            default_query_limit: self.default_query_limit,
+            database_id: self.database_id,
+            write_nulls: self.write_nulls.unwrap_or(true),
+            blocking_deserialize_threshold: self.blocking_deserialize_threshold,
+            dry_run: self.dry_run,
+            collection_prefix: self.collection_prefix,
+            max_document_bytes: self.max_document_bytes.unwrap_or(MAX_DOCUMENT_BYTES),
+            circuit_breaker: self.circuit_breaker,
+            read_time: self.read_time,
+        })
+    }
+}
+
+// Note: This testing setup just wont work unless you set everything up in firebase the exact same
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keep a test's own object type in `collection` of the in-memory store, keyed by its `key` field
+    /// Any extra config fields go in braces, e.g. `memory_fixture!(CappedOBJ, "memory_capped", { default_query_limit: Some(3) })`
+    macro_rules! memory_fixture {
+        ($obj:ident, $collection:expr) => {
+            memory_fixture!($obj, $collection, {});
+        };
+        ($obj:ident, $collection:expr, { $($field:ident: $value:expr),* $(,)? }) => {
+            impl CloudSync<String, InMemoryBackend> for $obj {
+                fn config() -> CLConfig {
+                    CLConfig { collection: $collection.to_string(), $($field: $value,)* ..MemOBJ::config() }
+                }
+            }
+
+            impl Unique<String> for $obj {
+                fn uuid(&self) -> String {
+                    String::from(&self.key)
+                }
+            }
+        };
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct TestOBJ {
+        key: String,
+        data: String,
+    }
+
+    impl CloudSync<String> for TestOBJ {
+        fn config() -> CLConfig {
+            CLConfig {
+                project_id: "cloudsync-testing".to_string(),
+                credentials: CredentialSource::File(PathBuf::from("./firebase.json")),
+                collection: "testing".to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl Unique<String> for TestOBJ {
+        fn uuid(&self) -> String {
+            String::from(&self.key)
+        }
+    }
+
+    // Super basic test...
+    // Add more at a later time?
+    #[tokio::test]
+    #[allow(non_snake_case, unused_must_use)]
+    async fn testSavingObject() {
+        let obj = TestOBJ {
+            key: "aaa".to_string(),
+            data: "data".to_string(),
+        };
+        obj.save().await;
+        let vec = TestOBJ::get().await.unwrap();
+        assert_eq!(vec.len(), 1);
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let count = || tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(TestOBJ::count()).unwrap();
+        shutdown();
+        let before = count();
+        // The first runtime is gone along with the client connected on it, which is rebuilt on the next call after a shutdown
+        shutdown();
+        assert_eq!(count(), before);
+    }
+
+    #[test]
+    fn test_config_from_env() {
+        std::env::remove_var("GOOGLE_CLOUD_PROJECT");
+        std::env::set_var("CLOUDSYNC_PROJECT_ID", "cloudsync-testing");
+        std::env::remove_var("GOOGLE_APPLICATION_CREDENTIALS");
+        match CLConfig::from_env("testing") {
+            Err(CloudSyncError::MissingEnvVar(var)) => assert_eq!(var, "GOOGLE_APPLICATION_CREDENTIALS"),
+            _ => panic!("expected a missing GOOGLE_APPLICATION_CREDENTIALS error"),
+        }
+
+        std::env::set_var("GOOGLE_APPLICATION_CREDENTIALS", "./firebase.json");
+        let cfg = CLConfig::from_env("testing").unwrap();
+        assert_eq!(cfg.project_id, "cloudsync-testing");
+        assert_eq!(cfg.credentials, CredentialSource::File(PathBuf::from("./firebase.json")));
+        assert_eq!(cfg.collection, "testing");
+    }
+
+    #[test]
+    fn test_config_builder() {
+        match CLConfig::builder().project_id("cloudsync-testing").build() {
+            Err(CloudSyncError::MissingConfig(missing)) => assert_eq!(missing, vec!["cred_path", "collection"]),
+            _ => panic!("expected missing cred_path and collection"),
+        }
+        let missing_file = CLConfig::builder().project_id("cloudsync-testing").cred_path("./no-such-file.json").collection("testing").build();
+        assert!(matches!(missing_file, Err(CloudSyncError::CredentialsNotFound(_))));
+
+        let cfg = CLConfig::builder()
+            .project_id("cloudsync-testing")
+            .credentials(CredentialSource::Adc)
+            .collection("testing")
+            .build()
+            .unwrap();
+        assert_eq!(cfg.project_id, "cloudsync-testing");
+        assert_eq!(cfg.collection, "testing");
+        assert_eq!(cfg.parent_path, None);
+        assert_eq!(cfg.scopes, None);
+
+        let scoped = CLConfig::builder()
+            .project_id("cloudsync-testing")
+            .credentials(CredentialSource::Adc)
+            .collection("testing")
+            .scopes(["https://www.googleapis.com/auth/datastore"])
+            .build()
+            .unwrap();
+        assert_eq!(scoped.scopes, Some(vec!["https://www.googleapis.com/auth/datastore".to_string()]));
+        assert_eq!(scoped.database_id, None);
+        let named = CLConfig::builder().project_id("cloudsync-testing").credentials(CredentialSource::Adc).collection("testing").database_id("dev").build();
+        assert_eq!(named.unwrap().database_id.as_deref(), Some("dev"));
+    }
+
+    #[test]
+    fn test_query_value() {
+        assert!(query_value(&"open").unwrap().is_some());
+        assert!(query_value(&Some(3)).unwrap().is_some());
+        assert!(query_value(&None::<i32>).unwrap().is_none());
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive_unique() {
+        #[derive(Unique)]
+        struct Named {
+            #[uuid]
+            key: String,
+            _data: String,
+        }
+
+        #[derive(Unique)]
+        struct Tuple(String, #[uuid] u64);
+
+        let named = Named { key: "aaa".to_string(), _data: "data".to_string() };
+        assert_eq!(named.uuid(), "aaa");
+        let tuple = Tuple("data".to_string(), 7);
+        assert_eq!(tuple.uuid(), 7);
+        assert_eq!(tuple.0, "data");
+    }
+
+    #[cfg(feature = "derive")]
+    #[tokio::test]
+    async fn test_derive_cloudsync() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Unique, CloudSync)]
+        #[cloudsync(collection = "memory_derived", project_id = "cloudsync-memory", cred_path = "./firebase.json", backend = "InMemoryBackend")]
+        struct DerivedOBJ {
+            #[uuid]
+            key: String,
+            data: String,
+        }
+
+        let cfg = DerivedOBJ::config();
+        assert_eq!(cfg.collection, "memory_derived");
+        assert_eq!(cfg.project_id, "cloudsync-memory");
+        assert_eq!(cfg.credentials, CredentialSource::File(PathBuf::from("./firebase.json")));
+
+        let obj = DerivedOBJ { key: "aaa".to_string(), data: "data".to_string() };
+        obj.save().await.unwrap();
+        assert_eq!(DerivedOBJ::get().await.unwrap(), vec![obj]);
+    }
+
+    #[tokio::test]
+    async fn test_global_config() {
+        // This is the only test setting the global config, which can't be unset again
+        assert!(matches!(CLConfig::with_collection("memory_global"), Err(CloudSyncError::GlobalConfigNotSet)));
+        set_global_config(GlobalConfig { project_id: "cloudsync-memory".to_string(), credentials: CredentialSource::Adc }).unwrap();
+        assert!(set_global_config(GlobalConfig::new("cloudsync-other", "./firebase.json")).is_err());
+        assert_eq!(global_config().unwrap().project_id, "cloudsync-memory");
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct GlobalOBJ {
+            key: String,
+        }
+
+        impl CloudSync<String, InMemoryBackend> for GlobalOBJ {
+            fn config() -> CLConfig {
+                CLConfig::with_collection("memory_global").unwrap()
+            }
+        }
+
+        impl Unique<String> for GlobalOBJ {
+            fn uuid(&self) -> String {
+                String::from(&self.key)
+            }
+        }
+
+        let cfg = GlobalOBJ::config();
+        assert_eq!((cfg.project_id.as_str(), cfg.collection.as_str()), ("cloudsync-memory", "memory_global"));
+        let obj = GlobalOBJ { key: "aaa".to_string() };
+        obj.save().await.unwrap();
+        assert_eq!(GlobalOBJ::get().await.unwrap(), vec![obj]);
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert!(policy.backoff(0) <= policy.initial_backoff);
+        assert!(policy.backoff(30) <= policy.max_backoff);
+        assert!(!retry::is_transient(&CloudSyncError::InvalidField("key".to_string())));
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        use firestore::errors::{FirestoreDatabaseError, FirestoreErrorPublicGenericDetails};
+
+        let cfg = CLConfig {
+            project_id: "cloudsync-circuit".to_string(),
+            circuit_breaker: Some(CircuitBreakerPolicy { failure_threshold: 2, cooldown: Duration::from_millis(50) }),
+            ..Default::default()
+        };
+        let unavailable = || -> Result<(), CloudSyncError> {
+            Err(FirestoreError::DatabaseError(FirestoreDatabaseError::new(
+                FirestoreErrorPublicGenericDetails::new("Unavailable".to_string()),
+                "down".to_string(),
+                true,
+            )).into())
+        };
+
+        // Answers that aren't transient don't count against firestore
+        circuit::record(&cfg, circuit::acquire(&cfg).unwrap(), &unavailable());
+        circuit::record::<()>(&cfg, circuit::acquire(&cfg).unwrap(), &Err(CloudSyncError::NotFound { collection: "c".to_string(), id: "a".to_string() }));
+        circuit::record(&cfg, circuit::acquire(&cfg).unwrap(), &unavailable());
+        // Calls let through before the circuit opened
+        let stale = circuit::acquire(&cfg).unwrap();
+        let late = circuit::acquire(&cfg).unwrap();
+
+        circuit::record(&cfg, circuit::acquire(&cfg).unwrap(), &unavailable());
+        assert!(matches!(circuit::acquire(&cfg), Err(CloudSyncError::CircuitOpen)));
+        // One of them succeeding late isn't a trial, so it leaves the circuit open
+        circuit::record(&cfg, late, &Ok(()));
+        assert!(matches!(circuit::acquire(&cfg), Err(CloudSyncError::CircuitOpen)));
+
+        // After the cooldown a single trial goes through, and failing it reopens the circuit
+        std::thread::sleep(Duration::from_millis(60));
+        let trial = circuit::acquire(&cfg).unwrap();
+        // The stale call finishing first doesn't end the trial
+        circuit::record(&cfg, stale, &unavailable());
+        assert!(matches!(circuit::acquire(&cfg), Err(CloudSyncError::CircuitOpen)));
+        circuit::record(&cfg, trial, &unavailable());
+        assert!(matches!(circuit::acquire(&cfg), Err(CloudSyncError::CircuitOpen)));
+
+        // A trial that's given up lets the next caller through as the trial
+        std::thread::sleep(Duration::from_millis(60));
+        drop(circuit::acquire(&cfg).unwrap());
+        let trial = circuit::acquire(&cfg).unwrap();
+        assert!(matches!(circuit::acquire(&cfg), Err(CloudSyncError::CircuitOpen)));
+
+        // A successful trial closes it again
+        circuit::record(&cfg, trial, &Ok(()));
+        assert!(circuit::acquire(&cfg).is_ok());
+        assert!(circuit::acquire(&cfg).is_ok());
+
+        // Configs without a breaker are never short-circuited
+        assert!(circuit::acquire(&CLConfig { circuit_breaker: None, ..cfg }).is_ok());
+    }
+
+    #[derive(Deserialize, Serialize)]
+    struct TenantOBJ {
+        key: String,
+        tenant: String,
+    }
+
+    impl CloudSync<String> for TenantOBJ {
+        fn config() -> CLConfig {
+            CLConfig {
+                project_id: "cloudsync-testing".to_string(),
+                credentials: CredentialSource::File(PathBuf::from("./firebase.json")),
+                collection: "testing_a".to_string(),
+                ..Default::default()
+            }
+        }
+
+        fn collection(&self) -> String {
+            format!("testing_{}", self.tenant)
+        }
+    }
+
+    impl Unique<String> for TenantOBJ {
+        fn uuid(&self) -> String {
+            String::from(&self.key)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_object_collection() {
+        // Don't reuse a client connected on another test's runtime
+        shutdown();
+        let a = TenantOBJ { key: "aaa".to_string(), tenant: "a".to_string() };
+        let b = TenantOBJ { key: "bbb".to_string(), tenant: "b".to_string() };
+        a.save().await.unwrap();
+        b.save().await.unwrap();
+        let saved = TenantOBJ::hash().await.unwrap();
+        assert!(saved.contains_key("aaa"));
+        assert!(!saved.contains_key("bbb"));
+        a.rm().await.unwrap();
+        b.rm().await.unwrap();
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct EmulatedOBJ {
+        key: String,
+        tags: Vec<String>,
+    }
+
+    impl CloudSync<String> for EmulatedOBJ {
+        fn config() -> CLConfig {
+            CLConfig {
+                project_id: "cloudsync-emulator".to_string(),
+                collection: "emulated".to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl Unique<String> for EmulatedOBJ {
+        fn uuid(&self) -> String {
+            String::from(&self.key)
+        }
+    }
+
+    // Only runs with FIRESTORE_EMULATOR_HOST set, e.g. against `gcloud emulators firestore start`,
+    // and needs no credentials file since the emulator doesn't check them
+    #[tokio::test]
+    async fn test_emulator() {
+        if std::env::var("FIRESTORE_EMULATOR_HOST").is_err() {
+            return;
+        }
+        let obj = EmulatedOBJ { key: "aaa".to_string(), tags: vec!["a".to_string()] };
+        obj.save().await.unwrap();
+        assert_eq!(EmulatedOBJ::get_by_id(&obj.key).await.unwrap(), Some(obj.clone()));
+        assert_eq!(EmulatedOBJ::get_where("tags", vec!["a"]).await.unwrap(), vec![obj.clone()]);
+        obj.rm().await.unwrap();
+        assert_eq!(EmulatedOBJ::get_by_id(&obj.key).await.unwrap(), None);
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct EmulatedTagsOBJ {
+        key: String,
+        tags: Vec<String>,
+    }
+
+    // A project of its own keeps its cached client apart from test_emulator's, which dies with that test's runtime
+    impl CloudSync<String> for EmulatedTagsOBJ {
+        fn config() -> CLConfig {
+            CLConfig {
+                project_id: "cloudsync-emulator-tags".to_string(),
+                collection: "emulated_tags".to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl Unique<String> for EmulatedTagsOBJ {
+        fn uuid(&self) -> String {
+            String::from(&self.key)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_emulator_concurrent_array_add() {
+        if std::env::var("FIRESTORE_EMULATOR_HOST").is_err() {
+            return;
+        }
+        let key = "concurrent".to_string();
+        EmulatedTagsOBJ { key: key.clone(), tags: vec![] }.save().await.unwrap();
+        let tags: Vec<String> = (0..50).map(|i| format!("tag{}", i)).collect();
+        let values: Vec<[&str; 2]> = tags.iter().map(|tag| [tag.as_str(), "shared"]).collect();
+        let adds = values.iter().map(|values| EmulatedTagsOBJ::array_add(&key, "tags", values));
+        futures::future::try_join_all(adds).await.unwrap();
+
+        let mut stored = EmulatedTagsOBJ::get_by_id(&key).await.unwrap().unwrap().tags;
+        stored.sort();
+        let mut expected = tags.clone();
+        expected.push("shared".to_string());
+        expected.sort();
+        assert_eq!(stored, expected);
+        EmulatedTagsOBJ::rm_by_id(&key).await.unwrap();
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct MemOBJ {
+        key: String,
+        data: String,
+        rank: i32,
+    }
+
+    impl CloudSync<String, InMemoryBackend> for MemOBJ {
+        fn config() -> CLConfig {
+            CLConfig {
+                project_id: "cloudsync-memory".to_string(),
+                collection: "memory".to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl Unique<String> for MemOBJ {
+        fn uuid(&self) -> String {
+            String::from(&self.key)
+        }
+    }
+
+    fn mem_obj(key: &str, rank: i32) -> MemOBJ {
+        MemOBJ { key: key.to_string(), data: "data".to_string(), rank }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_backend() {
+        let a = mem_obj("aaa", 2);
+        a.save().await.unwrap();
+        mem_obj("bbb", 1).save().await.unwrap();
+        mem_obj("ccc", 3).save().await.unwrap();
+
+        // Saving again overwrites rather than duplicating
+        let changed = MemOBJ { data: "changed".to_string(), ..a.clone() };
+        changed.save().await.unwrap();
+        assert_eq!(MemOBJ::count().await.unwrap(), 3);
+        assert_eq!(MemOBJ::get_by_id(&"aaa".to_string()).await.unwrap(), Some(changed));
+
+        // Missing documents are not errors
+        assert_eq!(MemOBJ::get_by_id(&"zzz".to_string()).await.unwrap(), None);
+        MemOBJ::rm_by_id(&"zzz".to_string()).await.unwrap();
+        assert!(matches!(MemOBJ::rm_by_id_strict(&"zzz".to_string()).await, Err(CloudSyncError::NotFound { .. })));
+
+        let ordered: Vec<String> = MemOBJ::get_ordered("rank", Order::Desc).await.unwrap().into_iter().map(|obj| obj.key).collect();
+        assert_eq!(ordered, vec!["ccc", "aaa", "bbb"]);
+        assert_eq!(MemOBJ::get_where("rank", 1).await.unwrap(), vec![mem_obj("bbb", 1)]);
+        let (page, cursor) = MemOBJ::get_page(2, None).await.unwrap();
+        assert_eq!(page.len(), 2);
+        let (page, cursor) = MemOBJ::get_page(2, cursor).await.unwrap();
+        assert_eq!(page, vec![mem_obj("ccc", 3)]);
+        assert_eq!(cursor, None);
+
+        a.rm().await.unwrap();
+        assert!(!MemOBJ::exists(&"aaa".to_string()).await.unwrap());
+        assert_eq!(MemOBJ::hash().await.unwrap().len(), 2);
+        assert_eq!(MemOBJ::get().await.unwrap().len(), MemOBJ::hash().await.unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_save_and_read() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ReadBackOBJ {
+            key: String,
+            data: String,
+        }
+
+        memory_fixture!(ReadBackOBJ, "memory_read_back");
+
+        let obj = ReadBackOBJ { key: "aaa".to_string(), data: "first".to_string() };
+        assert_eq!(obj.save_and_read().await.unwrap(), obj);
+        let changed = ReadBackOBJ { data: "changed".to_string(), ..obj.clone() };
+        assert_eq!(changed.save_and_read().await.unwrap(), changed);
+        assert_eq!(ReadBackOBJ::count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_first_where() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct FirstOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(FirstOBJ, "memory_first");
+
+        let first = |key: &str, rank: i32| FirstOBJ { key: key.to_string(), rank };
+        FirstOBJ::save_batch(&[first("aaa", 2), first("bbb", 1), first("ccc", 3)]).await.unwrap();
+        assert_eq!(FirstOBJ::first_where("rank", 1).await.unwrap(), Some(first("bbb", 1)));
+        assert_eq!(FirstOBJ::first_where("rank", 99).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_get_where_in() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ListedOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(ListedOBJ, "memory_where_in");
+
+        let listed = |key: &str, rank: i32| ListedOBJ { key: key.to_string(), rank };
+        ListedOBJ::save_batch(&[listed("aaa", 2), listed("bbb", 1), listed("ccc", 3), listed("ddd", 50)]).await.unwrap();
+        // More values than fit in one `in` clause, with repeats that must not duplicate results
+        let ranks: Vec<i32> = (0..40).chain([1, 3]).collect();
+        assert_eq!(ListedOBJ::get_where_in("rank", &ranks).await.unwrap().len(), 3);
+        assert!(ListedOBJ::get_where_in("rank", &[99]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        MemOBJ::ping().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_is_empty() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct EmptiedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(EmptiedOBJ, "memory_emptied");
+
+        assert!(EmptiedOBJ::is_empty().await.unwrap());
+        EmptiedOBJ { key: "aaa".to_string() }.save().await.unwrap();
+        assert!(!EmptiedOBJ::is_empty().await.unwrap());
+        let ids = list_collection_ids::<InMemoryBackend>(&EmptiedOBJ::config()).await.unwrap();
+        assert!(ids.contains(&"memory_emptied".to_string()));
+        assert!(!ids.contains(&"memory_nothing".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_all_paged() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct PagedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(PagedOBJ, "memory_all_paged");
+
+        let objs: Vec<_> = ["aaa", "bbb", "ccc"].iter().map(|key| PagedOBJ { key: key.to_string() }).collect();
+        PagedOBJ::save_batch(&objs).await.unwrap();
+        assert_eq!(PagedOBJ::get_all_paged(2).await.unwrap(), objs);
+        assert_eq!(PagedOBJ::get_all_paged(1).await.unwrap(), objs);
+        assert_eq!(PagedOBJ::get_all_paged(3).await.unwrap(), objs);
+    }
+
+    #[tokio::test]
+    async fn test_rm_counted() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct CountedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(CountedOBJ, "memory_counted");
+
+        let obj = CountedOBJ { key: "aaa".to_string() };
+        assert!(!obj.rm_counted().await.unwrap());
+        obj.save().await.unwrap();
+        assert!(obj.rm_counted().await.unwrap());
+        assert!(!CountedOBJ::exists(&obj.key).await.unwrap());
+        assert!(!obj.rm_counted().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cloudsync_ext() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ExtOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(ExtOBJ, "memory_ext");
+
+        let ext = |key: &str, rank: i32| ExtOBJ { key: key.to_string(), rank };
+        ExtOBJ::save_all((1..4).map(|rank| ext(&format!("obj{}", rank), rank))).await.unwrap();
+        assert_eq!(ExtOBJ::count().await.unwrap(), 3);
+        assert_eq!(ExtOBJ::find(|obj| obj.rank > 2).await.unwrap(), vec![ext("obj3", 3)]);
+        assert_eq!(ExtOBJ::get_or_default(&"zzz".to_string(), ext("zzz", 0)).await.unwrap(), ext("zzz", 0));
+        assert_eq!(ExtOBJ::get_or_default(&"obj1".to_string(), ext("zzz", 0)).await.unwrap(), ext("obj1", 1));
+    }
+
+    #[tokio::test]
+    async fn test_hash_by() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct KeyedOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(KeyedOBJ, "memory_hash_by");
+
+        let keyed = |key: &str, rank: i32| KeyedOBJ { key: key.to_string(), rank };
+        KeyedOBJ::save_batch(&[keyed("aaa", 1), keyed("bbb", 3), keyed("ccc", 3)]).await.unwrap();
+        let by_rank = KeyedOBJ::hash_by(|obj| obj.rank).await.unwrap();
+        assert_eq!(by_rank.get(&1), Some(&keyed("aaa", 1)));
+        // Objects sharing a key collapse into one
+        assert_eq!(by_rank.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rm_batch() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct BatchedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(BatchedOBJ, "memory_rm_batch");
+
+        let objs: Vec<_> = ["aaa", "bbb", "ccc"].iter().map(|key| BatchedOBJ { key: key.to_string() }).collect();
+        BatchedOBJ::save_batch(&objs).await.unwrap();
+        // Ids that aren't stored are skipped
+        BatchedOBJ::rm_batch(&["aaa".to_string(), "bbb".to_string(), "zzz".to_string()]).await.unwrap();
+        assert_eq!(BatchedOBJ::get().await.unwrap(), vec![objs[2].clone()]);
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct MemLockOBJ {
+        key: String,
+        data: String,
+    }
+
+    memory_fixture!(MemLockOBJ, "memory_lock");
+
+    #[tokio::test]
+    async fn test_in_memory_preconditions() {
+        let obj = MemLockOBJ { key: "aaa".to_string(), data: "first".to_string() };
+        assert!(obj.save_if_absent().await.unwrap());
+        assert!(!obj.save_if_absent().await.unwrap());
+
+        let (_, read_at) = MemLockOBJ::get_with_update_time(&obj.key).await.unwrap().unwrap();
+        let second = MemLockOBJ { data: "second".to_string(), ..obj.clone() };
+        second.save_checked(read_at).await.unwrap();
+        // The write above moved the update time on, so a writer still holding the old one conflicts
+        assert!(matches!(obj.save_checked(read_at).await, Err(CloudSyncError::Conflict { .. })));
+
+        // A compare-and-swap only goes through while the stored object is still the one it expects
+        let swapped = MemLockOBJ { data: "swapped".to_string(), ..obj.clone() };
+        assert!(matches!(swapped.save_if_matches(&obj).await, Err(CloudSyncError::Conflict { .. })));
+        swapped.save_if_matches(&second).await.unwrap();
+        assert_eq!(MemLockOBJ::get_by_id(&obj.key).await.unwrap(), Some(swapped));
+        let missing = MemLockOBJ { key: "zzz".to_string(), ..obj.clone() };
+        assert!(matches!(missing.save_if_matches(&missing).await, Err(CloudSyncError::NotFound { .. })));
+
+        let partial = MemLockOBJ { data: "third".to_string(), ..obj.clone() };
+        partial.update(&["data"]).await.unwrap();
+        assert_eq!(MemLockOBJ::get_by_id(&obj.key).await.unwrap(), Some(partial));
+        assert!(matches!(obj.update(&["missing"]).await, Err(CloudSyncError::InvalidField(_))));
+
+        let (_, meta) = MemLockOBJ::get_with_meta(&obj.key).await.unwrap().unwrap();
+        assert_eq!(meta.id, "aaa");
+        assert_eq!(meta.path, "projects/cloudsync-memory/databases/(default)/documents/memory_lock/aaa");
+        assert!(meta.create_time < meta.update_time);
+        assert_eq!(MemLockOBJ::get_with_meta(&"zzz".to_string()).await.unwrap(), None);
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct OrderOBJ {
+        key: String,
+        total: i32,
+    }
+
+    memory_fixture!(OrderOBJ, "orders", { parent_path: Some("users/alice".to_string()) });
+
+    #[tokio::test]
+    async fn test_subcollection() {
+        let order = OrderOBJ { key: "o1".to_string(), total: 20 };
+        order.save().await.unwrap();
+        assert_eq!(OrderOBJ::get().await.unwrap(), vec![order.clone()]);
+        assert!(OrderOBJ::hash().await.unwrap().contains_key("o1"));
+
+        // The document lives at users/alice/orders/o1, not in a top-level orders collection
+        let backend = InMemoryBackend::connect(&OrderOBJ::config()).await.unwrap();
+        let root = backend.documents_path().to_string();
+        let doc = backend.get_doc(&format!("{}/users/alice", root), "orders", "o1").await.unwrap().unwrap();
+        assert_eq!(doc.name, format!("{}/users/alice/orders/o1", root));
+        assert!(backend.get_doc(&root, "orders", "o1").await.unwrap().is_none());
+        assert!(!OrderOBJ::is_empty().await.unwrap());
+        assert_eq!(list_collection_ids::<InMemoryBackend>(&OrderOBJ::config()).await.unwrap(), vec!["orders"]);
+
+        order.rm().await.unwrap();
+        assert!(OrderOBJ::get().await.unwrap().is_empty());
+        assert!(OrderOBJ::is_empty().await.unwrap());
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct SyncOBJ {
+        key: String,
+        #[serde(with = "firestore::serialize_as_timestamp")]
+        created: DateTime<Utc>,
+        #[serde(with = "firestore::serialize_as_timestamp")]
+        updated: DateTime<Utc>,
+    }
+
+    memory_fixture!(SyncOBJ, "memory_sync");
+
+    impl Timestamped for SyncOBJ {
+        fn set_created(&mut self, t: DateTime<Utc>) {
+            self.created = t;
+        }
+
+        fn set_updated(&mut self, t: DateTime<Utc>) {
+            self.updated = t;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_modified_since() {
+        let start = Utc::now() - chrono::Duration::hours(1);
+        let mut old = SyncOBJ { key: "old".to_string(), created: start, updated: start };
+        old.save().await.unwrap();
+        let mut new = SyncOBJ { key: "new".to_string(), ..old.clone() };
+        new.save_timestamped().await.unwrap();
+        assert!(new.created > start);
+
+        let changed = SyncOBJ::get_modified_since("updated", start).await.unwrap();
+        assert_eq!(changed, vec![new.clone()]);
+        old.save_timestamped().await.unwrap();
+        // Saving again keeps the original created time but moves updated on, so it shows up as changed
+        assert_eq!(old.created, start);
+        let changed: Vec<String> = SyncOBJ::get_modified_since("updated", start).await.unwrap().into_iter().map(|obj| obj.key).collect();
+        assert_eq!(changed, vec!["new", "old"]);
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct ListenOBJ {
+        key: String,
+        data: String,
+    }
+
+    memory_fixture!(ListenOBJ, "memory_listen");
+
+    #[tokio::test]
+    async fn test_listen() {
+        let first = ListenOBJ { key: "aaa".to_string(), data: "data".to_string() };
+        first.save().await.unwrap();
+        let existing = ListenOBJ { key: "aab".to_string(), data: "data".to_string() };
+        existing.save().await.unwrap();
+        let mut changes = ListenOBJ::listen().await.unwrap();
+        // Everything already saved comes first, before any live change
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Added(first.clone()));
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Added(existing));
+
+        let second = ListenOBJ { key: "bbb".to_string(), data: "data".to_string() };
+        second.save().await.unwrap();
+        let changed = ListenOBJ { data: "changed".to_string(), ..first.clone() };
+        changed.save().await.unwrap();
+        first.rm().await.unwrap();
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Added(second));
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Modified(changed));
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Removed("aaa".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct WatchedOBJ {
+            key: String,
+            data: String,
+        }
+
+        memory_fixture!(WatchedOBJ, "memory_watched");
+
+        let watched = |key: &str, data: &str| WatchedOBJ { key: key.to_string(), data: data.to_string() };
+        WatchedOBJ::save_batch(&[watched("aaa", "data"), watched("bbb", "data")]).await.unwrap();
+        let mut changes = WatchedOBJ::watch().await.unwrap();
+        // The snapshot first, every stored object as Added
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Added(watched("aaa", "data")));
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Added(watched("bbb", "data")));
+
+        // Then the live changes
+        watched("ccc", "data").save().await.unwrap();
+        watched("aaa", "changed").save().await.unwrap();
+        WatchedOBJ::rm_by_id(&"bbb".to_string()).await.unwrap();
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Added(watched("ccc", "data")));
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Modified(watched("aaa", "changed")));
+        assert_eq!(changes.next().await.unwrap().unwrap(), ChangeEvent::Removed("bbb".to_string()));
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct CounterOBJ {
+        key: String,
+        views: i64,
+        label: String,
+    }
+
+    memory_fixture!(CounterOBJ, "memory_counter");
+
+    #[tokio::test]
+    async fn test_increment() {
+        let key = "aaa".to_string();
+        // A missing document is created with the field counting up from zero
+        CounterOBJ::increment(&key, "views", 2).await.unwrap();
+        CounterOBJ { key: key.clone(), views: 0, label: "page".to_string() }.update(&["key", "label"]).await.unwrap();
+        CounterOBJ::increment(&key, "views", -5).await.unwrap();
+        assert_eq!(CounterOBJ::get_by_id(&key).await.unwrap().unwrap().views, -3);
+        assert!(matches!(CounterOBJ::increment(&key, "label", 1).await, Err(CloudSyncError::WrongFieldType { .. })));
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct TaggedOBJ {
+        key: String,
+        tags: Vec<String>,
+    }
+
+    memory_fixture!(TaggedOBJ, "memory_tagged");
+
+    #[tokio::test]
+    async fn test_array_add_and_remove() {
+        let key = "aaa".to_string();
+        TaggedOBJ { key: key.clone(), tags: vec!["old".to_string()] }.save().await.unwrap();
+        // Two writers adding at the same time both land, where two whole-object saves would drop one of them
+        let (a, b) = tokio::join!(TaggedOBJ::array_add(&key, "tags", &["red", "old"]), TaggedOBJ::array_add(&key, "tags", &["blue"]));
+        a.unwrap();
+        b.unwrap();
+        let mut tags = TaggedOBJ::get_by_id(&key).await.unwrap().unwrap().tags;
+        tags.sort();
+        assert_eq!(tags, vec!["blue", "old", "red"]);
+
+        TaggedOBJ::array_remove(&key, "tags", &["old", "missing"]).await.unwrap();
+        assert_eq!(TaggedOBJ::get_by_id(&key).await.unwrap().unwrap().tags.len(), 2);
+        assert!(matches!(TaggedOBJ::array_add(&key, "key", &["x"]).await, Err(CloudSyncError::WrongFieldType { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_array_add() {
+        let key = "concurrent".to_string();
+        TaggedOBJ { key: key.clone(), tags: vec![] }.save().await.unwrap();
+        // A unit-level stand-in that only checks the transforms cloudsync sends; test_emulator_concurrent_array_add
+        // runs the same adds against firestore's own scheduling
+        let tags: Vec<String> = (0..50).map(|i| format!("tag{}", i)).collect();
+        let values: Vec<[&str; 2]> = tags.iter().map(|tag| [tag.as_str(), "shared"]).collect();
+        let adds = values.iter().map(|values| TaggedOBJ::array_add(&key, "tags", values));
+        futures::future::try_join_all(adds).await.unwrap();
+
+        let mut stored = TaggedOBJ::get_by_id(&key).await.unwrap().unwrap().tags;
+        stored.sort();
+        let mut expected = tags.clone();
+        expected.push("shared".to_string());
+        expected.sort();
+        assert_eq!(stored, expected);
+    }
+
+    #[tokio::test]
+    async fn test_get_arc() {
+        let key = "arc".to_string();
+        TaggedOBJ { key: key.clone(), tags: vec!["cached".to_string()] }.save().await.unwrap();
+
+        let objects = TaggedOBJ::get_arc().await.unwrap();
+        assert!(objects.iter().any(|obj| obj.key == key));
+        let hash = TaggedOBJ::hash_arc().await.unwrap();
+        let shared = Arc::clone(&hash[&key]);
+        assert_eq!(shared.tags, vec!["cached"]);
+        assert_eq!(Arc::strong_count(&shared), 2);
+    }
+
+    #[tokio::test]
+    async fn test_hash_strict() {
+        // Documents written outside of cloudsync can end up with an id that doesn't match the uuid inside them
+        let cfg = CLConfig { collection: "memory_duplicates".to_string(), ..MemOBJ::config() };
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let root = backend.documents_path().to_string();
+        backend.commit(vec![
+            set_write(&cfg, &root, &cfg.collection, "first", &mem_obj("aaa", 1)).unwrap(),
+            set_write(&cfg, &root, &cfg.collection, "second", &mem_obj("aaa", 2)).unwrap(),
+        ]).await.unwrap();
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct DuplicateOBJ {
+            key: String,
+            data: String,
+            rank: i32,
+        }
+
+        memory_fixture!(DuplicateOBJ, "memory_duplicates");
+
+        assert_eq!(DuplicateOBJ::hash().await.unwrap().len(), 1);
+        match DuplicateOBJ::hash_strict().await {
+            Err(CloudSyncError::DuplicateUuid(id)) => assert_eq!(id, "aaa"),
+            _ => panic!("expected a duplicate uuid error"),
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+    struct LineKey {
+        order: String,
+        line: u32,
+    }
+
+    impl ToDocId for LineKey {
+        fn to_doc_id(&self) -> String {
+            format!("{}-{}", self.order, self.line)
+        }
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+    struct LineOBJ {
+        key: LineKey,
+        item: String,
+    }
+
+    impl CloudSync<LineKey, InMemoryBackend> for LineOBJ {
+        fn config() -> CLConfig {
+            CLConfig { collection: "memory_lines".to_string(), ..MemOBJ::config() }
+        }
+    }
+
+    impl Unique<LineKey> for LineOBJ {
+        fn uuid(&self) -> LineKey {
+            self.key.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_composite_uuid() {
+        let key = LineKey { order: "o1".to_string(), line: 2 };
+        let obj = LineOBJ { key: key.clone(), item: "apple".to_string() };
+        assert_eq!(obj.doc_id(), "o1-2");
+        obj.save().await.unwrap();
+
+        assert_eq!(LineOBJ::get_by_id(&key).await.unwrap(), Some(obj.clone()));
+        let hash = LineOBJ::hash().await.unwrap();
+        assert_eq!(hash.get(&key), Some(&obj));
+
+        let cfg = LineOBJ::config();
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        assert!(backend.get_doc(&parent, &cfg.collection, "o1-2").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_get_projected() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ProjectedOBJ {
+            key: String,
+            data: String,
+            rank: i32,
+        }
+
+        memory_fixture!(ProjectedOBJ, "memory_projected");
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Summary {
+            key: String,
+            rank: i32,
+        }
+
+        ProjectedOBJ { key: "aaa".to_string(), data: "a lot of data".to_string(), rank: 1 }.save().await.unwrap();
+        ProjectedOBJ { key: "bbb".to_string(), data: "even more data".to_string(), rank: 2 }.save().await.unwrap();
+
+        let summaries: Vec<Summary> = ProjectedOBJ::get_projected(&["key", "rank"]).await.unwrap();
+        assert_eq!(summaries, vec![
+            Summary { key: "aaa".to_string(), rank: 1 },
+            Summary { key: "bbb".to_string(), rank: 2 },
+        ]);
+
+        // Fields outside the projection aren't returned, so they can't be deserialized
+        assert!(ProjectedOBJ::get_projected::<ProjectedOBJ>(&["key", "rank"]).await.is_err());
+
+        let raw = ProjectedOBJ::get_raw(&["data"]).await.unwrap();
+        assert_eq!(raw, vec![serde_json::json!({ "data": "a lot of data" }), serde_json::json!({ "data": "even more data" })]);
+    }
+
+    #[tokio::test]
+    async fn test_operation_timeout() {
+        let hung = std::future::pending::<Result<(), CloudSyncError>>();
+        match trace::traced("hang", "memory", None, Some(Duration::from_millis(10)), hung).await {
+            Err(CloudSyncError::Timeout(timeout)) => assert_eq!(timeout, Duration::from_millis(10)),
+            _ => panic!("expected a timeout"),
+        }
+
+        let done = async { Ok(7) };
+        assert_eq!(trace::traced("done", "memory", None, Some(Duration::from_secs(5)), done).await.unwrap(), 7);
+        assert_eq!(MemOBJ::config().operation_timeout, None);
+    }
+
+    #[tokio::test]
+    async fn test_save_transactional() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct AccountOBJ {
+            key: String,
+            balance: i64,
+        }
+
+        memory_fixture!(AccountOBJ, "memory_accounts");
+
+        let account = |key: &str, balance| AccountOBJ { key: key.to_string(), balance };
+        AccountOBJ::save_transactional(&[account("aaa", 50), account("bbb", 150)]).await.unwrap();
+        assert_eq!(AccountOBJ::get().await.unwrap(), vec![account("aaa", 50), account("bbb", 150)]);
+
+        let too_many: Vec<_> = (0..501).map(|i| account(&format!("many{:03}", i), i)).collect();
+        match AccountOBJ::save_transactional(&too_many).await {
+            Err(CloudSyncError::TooManyWrites { count: 501, max: 500 }) => {}
+            _ => panic!("expected too many writes"),
+        }
+        assert_eq!(AccountOBJ::count().await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rm_where() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct TaskOBJ {
+            key: String,
+            done: bool,
+            age: i64,
+        }
+
+        memory_fixture!(TaskOBJ, "memory_tasks");
+
+        let task = |key: &str, done, age| TaskOBJ { key: key.to_string(), done, age };
+        TaskOBJ::save_batch(&[task("aaa", true, 10), task("bbb", false, 12), task("ccc", true, 2), task("ddd", true, 8)]).await.unwrap();
+
+        assert_eq!(TaskOBJ::rm_where("age", FilterOp::Gt, 7).await.unwrap(), 3);
+        assert_eq!(TaskOBJ::get().await.unwrap(), vec![task("ccc", true, 2)]);
+        assert_eq!(TaskOBJ::rm_where("done", FilterOp::Eq, false).await.unwrap(), 0);
+        assert_eq!(TaskOBJ::rm_where("key", FilterOp::In, ["ccc", "zzz"]).await.unwrap(), 1);
+        assert_eq!(TaskOBJ::count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_sync() {
+        let cfg = CLConfig { collection: "memory_synced".to_string(), ..MemOBJ::config() };
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct SyncedOBJ {
+            key: String,
+            data: String,
+        }
+
+        memory_fixture!(SyncedOBJ, "memory_synced");
+
+        let synced = |key: &str, data: &str| SyncedOBJ { key: key.to_string(), data: data.to_string() };
+        SyncedOBJ::save_batch(&[synced("aaa", "old"), synced("bbb", "same"), synced("ccc", "gone")]).await.unwrap();
+
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        let before = backend.get_doc(&parent, &cfg.collection, "bbb").await.unwrap().unwrap();
+
+        let report = SyncedOBJ::sync(&[synced("aaa", "new"), synced("bbb", "same"), synced("ddd", "added")]).await.unwrap();
+        assert_eq!(report, SyncReport { created: 1, updated: 1, deleted: 1, unchanged: 1 });
+        assert_eq!(SyncedOBJ::get().await.unwrap(), vec![synced("aaa", "new"), synced("bbb", "same"), synced("ddd", "added")]);
+
+        // The unchanged object wasn't written again
+        let after = backend.get_doc(&parent, &cfg.collection, "bbb").await.unwrap().unwrap();
+        assert_eq!(before.update_time, after.update_time);
+
+        let report = SyncedOBJ::sync(&[]).await.unwrap();
+        assert_eq!(report, SyncReport { deleted: 3, ..Default::default() });
+    }
+
+    #[tokio::test]
+    async fn test_sync_per_object_collection() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ShardedOBJ {
+            key: String,
+            shard: String,
+        }
+
+        impl CloudSync<String, InMemoryBackend> for ShardedOBJ {
+            fn config() -> CLConfig {
+                CLConfig { collection: "memory_sharded".to_string(), ..MemOBJ::config() }
+            }
+
+            fn collection(&self) -> String {
+                format!("memory_sharded_{}", self.shard)
+            }
+        }
+
+        impl Unique<String> for ShardedOBJ {
+            fn uuid(&self) -> String {
+                String::from(&self.key)
+            }
+        }
+
+        let sharded = |key: &str, shard: &str| ShardedOBJ { key: key.to_string(), shard: shard.to_string() };
+        sharded("aaa", "a").save().await.unwrap();
+        sharded("stale", "a").save().await.unwrap();
+
+        let report = ShardedOBJ::sync(&[sharded("aaa", "a"), sharded("bbb", "b")]).await.unwrap();
+        assert_eq!(report, SyncReport { created: 1, deleted: 1, unchanged: 1, ..Default::default() });
+
+        // Each object lands in the collection save() writes it to, not the config's
+        let cfg = ShardedOBJ::config();
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        assert!(backend.get_doc(&parent, "memory_sharded_a", "aaa").await.unwrap().is_some());
+        assert!(backend.get_doc(&parent, "memory_sharded_a", "stale").await.unwrap().is_none());
+        assert!(backend.get_doc(&parent, "memory_sharded_b", "bbb").await.unwrap().is_some());
+        assert_eq!(ShardedOBJ::count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_where_all() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct IssueOBJ {
+            key: String,
+            status: String,
+            priority: i32,
+        }
+
+        memory_fixture!(IssueOBJ, "memory_issues");
+
+        let issue = |key: &str, status: &str, priority| IssueOBJ { key: key.to_string(), status: status.to_string(), priority };
+        IssueOBJ::save_batch(&[issue("aaa", "open", 5), issue("bbb", "open", 1), issue("ccc", "closed", 9)]).await.unwrap();
+
+        let filters = [
+            Filter::new("status", FilterOp::Eq, "open").unwrap(),
+            Filter::new("priority", FilterOp::Gt, 3).unwrap(),
+        ];
+        assert_eq!(IssueOBJ::get_where_all(&filters).await.unwrap(), vec![issue("aaa", "open", 5)]);
+        assert_eq!(IssueOBJ::get_where_all(&[]).await.unwrap().len(), 3);
+
+        let filters = [
+            Filter::new("priority", FilterOp::Gt, 3).unwrap(),
+            Filter::new("status", FilterOp::NotEq, "closed").unwrap(),
+        ];
+        assert!(matches!(IssueOBJ::get_where_all(&filters).await, Err(CloudSyncError::InvalidQuery(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_where_range() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ProductOBJ {
+            key: String,
+            price: f64,
+        }
+
+        memory_fixture!(ProductOBJ, "memory_products");
+
+        let product = |key: &str, price| ProductOBJ { key: key.to_string(), price };
+        ProductOBJ::save_batch(&[product("aaa", 30.0), product("bbb", 10.0), product("ccc", 20.0), product("ddd", 40.0)]).await.unwrap();
+
+        let keys = |objs: Vec<ProductOBJ>| objs.into_iter().map(|obj| obj.key).collect::<Vec<_>>();
+        assert_eq!(keys(ProductOBJ::get_where_range("price", 20.0, 30.0).await.unwrap()), vec!["ccc", "aaa"]);
+        assert_eq!(keys(ProductOBJ::get_where_gt("price", 20.0).await.unwrap()), vec!["aaa", "ddd"]);
+        assert_eq!(keys(ProductOBJ::get_where_lt("price", 20.0).await.unwrap()), vec!["bbb"]);
+        assert!(ProductOBJ::get_where_range("price", 50.0, 60.0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_builder() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ItemOBJ {
+            key: String,
+            kind: String,
+            price: i32,
+        }
+
+        memory_fixture!(ItemOBJ, "memory_items");
+
+        let item = |key: &str, kind: &str, price| ItemOBJ { key: key.to_string(), kind: kind.to_string(), price };
+        ItemOBJ::save_batch(&[item("aaa", "book", 30), item("bbb", "book", 10), item("ccc", "pen", 5), item("ddd", "book", 20)]).await.unwrap();
+        let keys = |objs: Vec<ItemOBJ>| objs.into_iter().map(|obj| obj.key).collect::<Vec<_>>();
+
+        let books = ItemOBJ::query().filter("kind", FilterOp::Eq, "book").order_by("price", Order::Desc);
+        assert_eq!(keys(books.fetch().await.unwrap()), vec!["aaa", "ddd", "bbb"]);
+        let page = ItemOBJ::query().filter("kind", FilterOp::Eq, "book").order_by("price", Order::Desc).start_after(30).limit(1);
+        assert_eq!(keys(page.fetch().await.unwrap()), vec!["ddd"]);
+        // Without an order the cursor is a document id, and range filters order by their field
+        assert_eq!(keys(ItemOBJ::query().start_after("bbb").fetch().await.unwrap()), vec!["ccc", "ddd"]);
+        assert_eq!(keys(ItemOBJ::query().filter("price", FilterOp::Gt, 5).limit(2).fetch().await.unwrap()), vec!["bbb", "ddd"]);
+
+        let two_ranges = ItemOBJ::query().filter("price", FilterOp::Gt, 5).filter("kind", FilterOp::Lt, "z").fetch().await;
+        assert!(matches!(two_ranges, Err(CloudSyncError::InvalidQuery(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_field() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct Address {
+            city: String,
+        }
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct FieldOBJ {
+            key: String,
+            status: String,
+            address: Address,
+        }
+
+        memory_fixture!(FieldOBJ, "memory_fields");
+
+        let id = "aaa".to_string();
+        FieldOBJ { key: id.clone(), status: "open".to_string(), address: Address { city: "Oslo".to_string() } }.save().await.unwrap();
+
+        assert_eq!(FieldOBJ::get_field::<String>(&id, "status").await.unwrap(), Some("open".to_string()));
+        assert_eq!(FieldOBJ::get_field::<String>(&id, "address.city").await.unwrap(), Some("Oslo".to_string()));
+        assert_eq!(FieldOBJ::get_field::<Address>(&id, "address").await.unwrap(), Some(Address { city: "Oslo".to_string() }));
+        assert_eq!(FieldOBJ::get_field::<String>(&id, "missing").await.unwrap(), None);
+        assert_eq!(FieldOBJ::get_field::<String>(&"bbb".to_string(), "status").await.unwrap(), None);
+        assert!(FieldOBJ::get_field::<i32>(&id, "status").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_time() {
+        let earlier = Utc::now() - chrono::Duration::minutes(5);
+        let cfg = CLConfig::builder().project_id("cloudsync-testing").credentials(CredentialSource::Adc).collection("testing").read_time(earlier).build();
+        assert_eq!(cfg.unwrap().read_time, Some(earlier));
+
+        // There's no history to read from in memory, which is refused rather than quietly reading the latest data
+        assert!(matches!(MemOBJ::query().at_time(earlier).fetch().await, Err(CloudSyncError::Unsupported(_))));
+        let snapshot: Collection<MemOBJ, String, InMemoryBackend> = Collection::new(CLConfig { read_time: Some(earlier), ..MemOBJ::config() });
+        assert!(matches!(snapshot.count().await, Err(CloudSyncError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_missing_index() {
+        let details = "status: FailedPrecondition, message: \"The query requires an index. You can create it here: https://console.firebase.google.com/v1/r/project/cloudsync-testing/firestore/indexes?create_composite=abc\"";
+        let err = FirestoreError::DatabaseError(firestore::errors::FirestoreDatabaseError::new(
+            firestore::errors::FirestoreErrorPublicGenericDetails::new("FailedPrecondition".to_string()),
+            details.to_string(),
+            false,
+        ));
+        match missing_index(err.into()) {
+            CloudSyncError::MissingIndex(url) => assert_eq!(
+                url.as_deref(),
+                Some("https://console.firebase.google.com/v1/r/project/cloudsync-testing/firestore/indexes?create_composite=abc"),
+            ),
+            _ => panic!("expected a missing index error"),
+        }
+        assert!(matches!(missing_index(CloudSyncError::InvalidField("key".to_string())), CloudSyncError::InvalidField(_)));
+    }
+
+    #[tokio::test]
+    async fn test_soft_rm() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct NoteOBJ {
+            key: String,
+            text: String,
+        }
+
+        memory_fixture!(NoteOBJ, "memory_notes");
+
+        let note = |key: &str| NoteOBJ { key: key.to_string(), text: "text".to_string() };
+        NoteOBJ::save_batch(&[note("aaa"), note("bbb")]).await.unwrap();
+
+        note("aaa").soft_rm().await.unwrap();
+        assert_eq!(NoteOBJ::get_active().await.unwrap(), vec![note("bbb")]);
+        assert_eq!(NoteOBJ::get().await.unwrap(), vec![note("aaa"), note("bbb")]);
+
+        note("aaa").restore().await.unwrap();
+        assert_eq!(NoteOBJ::get_active().await.unwrap(), vec![note("aaa"), note("bbb")]);
+
+        assert!(matches!(note("zzz").soft_rm().await, Err(CloudSyncError::NotFound { .. })));
+        assert!(matches!(note("zzz").restore().await, Err(CloudSyncError::NotFound { .. })));
+        assert!(!NoteOBJ::exists(&"zzz".to_string()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_doc_id_override() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct PrefixedOBJ {
+            key: String,
+        }
+
+        impl CloudSync<String, InMemoryBackend> for PrefixedOBJ {
+            fn config() -> CLConfig {
+                CLConfig { collection: "memory_prefixed".to_string(), ..MemOBJ::config() }
+            }
+        }
+
+        impl Unique<String> for PrefixedOBJ {
+            fn uuid(&self) -> String {
+                String::from(&self.key)
+            }
+
+            fn doc_id_for(uuid: &String) -> String {
+                format!("user-{}", uuid)
+            }
+        }
+
+        let obj = PrefixedOBJ { key: "aaa".to_string() };
+        assert_eq!(obj.doc_id(), "user-aaa");
+        obj.save().await.unwrap();
+
+        let cfg = PrefixedOBJ::config();
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        assert!(backend.get_doc(&parent, &cfg.collection, "user-aaa").await.unwrap().is_some());
+
+        let id = "aaa".to_string();
+        assert_eq!(PrefixedOBJ::get_by_id(&id).await.unwrap(), Some(obj.clone()));
+        assert_eq!(PrefixedOBJ::get_many_by_ids(std::slice::from_ref(&id)).await.unwrap(), vec![obj]);
+        PrefixedOBJ::rm_by_id_strict(&id).await.unwrap();
+        assert!(!PrefixedOBJ::exists(&id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_observer() {
+        #[derive(Default)]
+        struct Recorder {
+            seen: std::sync::Mutex<Vec<(&'static str, bool)>>,
+        }
+
+        impl Observer for std::sync::Arc<Recorder> {
+            fn on_operation(&self, op: &'static str, collection: &str, _duration: Duration, result: Result<(), &CloudSyncError>) {
+                // Other tests run at the same time, so only look at this test's collection
+                if collection == "memory_observed" {
+                    self.seen.lock().unwrap().push((op, result.is_ok()));
+                }
+            }
+        }
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ObservedOBJ {
+            key: String,
+            created: DateTime<Utc>,
+            updated: DateTime<Utc>,
+        }
+
+        memory_fixture!(ObservedOBJ, "memory_observed");
+
+        impl Timestamped for ObservedOBJ {
+            fn set_created(&mut self, t: DateTime<Utc>) {
+                self.created = t;
+            }
+
+            fn set_updated(&mut self, t: DateTime<Utc>) {
+                self.updated = t;
+            }
+        }
+
+        let recorder = std::sync::Arc::new(Recorder::default());
+        set_observer(recorder.clone());
+        let mut obj = ObservedOBJ { key: "aaa".to_string(), created: Utc::now(), updated: Utc::now() };
+        let key = obj.key.clone();
+        obj.save().await.unwrap();
+        obj.save_timestamped().await.unwrap();
+        obj.save_and_read().await.unwrap();
+        ObservedOBJ::hash().await.unwrap();
+        ObservedOBJ::hash_strict().await.unwrap();
+        ObservedOBJ::hash_by(|obj| obj.created).await.unwrap();
+        ObservedOBJ::get_where_in("key", &[key.as_str()]).await.unwrap();
+        ObservedOBJ::get_with_update_time(&key).await.unwrap();
+        ObservedOBJ::get_all_paged(1).await.unwrap();
+        assert!(ObservedOBJ::rm_by_id_strict(&"missing".to_string()).await.is_err());
+        clear_observer();
+        ObservedOBJ::get().await.unwrap();
+
+        // Operations built on others report only themselves, once each
+        let expected = vec![
+            ("save", true),
+            ("save_timestamped", true),
+            ("save_and_read", true),
+            ("hash", true),
+            ("hash_strict", true),
+            ("hash_by", true),
+            ("get_where_in", true),
+            ("get_with_update_time", true),
+            ("get_all_paged", true),
+            ("rm_by_id_strict", false),
+        ];
+        assert_eq!(*recorder.seen.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_get_limit() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct CappedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(CappedOBJ, "memory_capped", { default_query_limit: Some(3) });
+
+        let objs: Vec<_> = (0..5).map(|i| CappedOBJ { key: format!("obj{}", i) }).collect();
+        CappedOBJ::save_batch(&objs).await.unwrap();
+
+        assert_eq!(CappedOBJ::get_limit(2).await.unwrap(), objs[..2]);
+        assert_eq!(CappedOBJ::get_limit(4).await.unwrap(), objs[..4]);
+        assert_eq!(CappedOBJ::query().fetch().await.unwrap(), objs[..3]);
+        assert_eq!(CappedOBJ::get().await.unwrap(), objs[..3]);
+        assert_eq!(CappedOBJ::hash().await.unwrap().len(), 3);
+        assert_eq!(CappedOBJ::get_active().await.unwrap(), objs[..3]);
+        assert_eq!(CappedOBJ::get_lenient().await.unwrap().0, objs[..3]);
+        assert_eq!(CappedOBJ::count().await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_get_page_before() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct PagedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(PagedOBJ, "memory_paged");
+
+        let objs: Vec<_> = (0..5).map(|i| PagedOBJ { key: format!("obj{}", i) }).collect();
+        PagedOBJ::save_batch(&objs).await.unwrap();
+
+        // Walk forward, then back from the first object of each page, landing on the same boundaries
+        let mut pages = vec![];
+        let mut cursor = None;
+        loop {
+            let (page, next) = PagedOBJ::get_page(2, cursor).await.unwrap();
+            pages.push(page);
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(pages, vec![objs[..2].to_vec(), objs[2..4].to_vec(), objs[4..].to_vec()]);
+
+        let (page, prev) = PagedOBJ::get_page_before(2, pages[2][0].uuid()).await.unwrap();
+        assert_eq!(page, pages[1]);
+        assert_eq!(prev, Some(pages[1][0].uuid()));
+        let (page, prev) = PagedOBJ::get_page_before(2, prev.unwrap()).await.unwrap();
+        assert_eq!(page, pages[0]);
+        let (page, prev) = PagedOBJ::get_page_before(2, prev.unwrap()).await.unwrap();
+        assert!(page.is_empty());
+        assert_eq!(prev, None);
+
+        // A short page before the cursor means the start was reached
+        assert_eq!(PagedOBJ::get_page_before(2, "obj1".to_string()).await.unwrap(), (objs[..1].to_vec(), None));
+    }
+
+    #[tokio::test]
+    async fn test_get_random() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct SampledOBJ {
+            key: String,
+        }
+
+        memory_fixture!(SampledOBJ, "memory_sampled");
+
+        assert_eq!(SampledOBJ::get_random().await.unwrap(), None);
+
+        // Sorts before every random id, so it's only found by wrapping around
+        let first = SampledOBJ { key: "!".to_string() };
+        first.save().await.unwrap();
+        assert_eq!(SampledOBJ::get_random().await.unwrap(), Some(first));
+
+        let objs: Vec<_> = ["a", "M", "z", "5"].iter().map(|key| SampledOBJ { key: key.to_string() }).collect();
+        SampledOBJ::save_batch(&objs).await.unwrap();
+        let stored = SampledOBJ::get().await.unwrap();
+        for _ in 0..20 {
+            assert!(stored.contains(&SampledOBJ::get_random().await.unwrap().unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_blocking_deserialize() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct BulkOBJ {
+            key: String,
+        }
+
+        memory_fixture!(BulkOBJ, "memory_bulk", { blocking_deserialize_threshold: Some(3) });
+
+        let objs: Vec<_> = (0..5).map(|i| BulkOBJ { key: format!("obj{}", i) }).collect();
+        BulkOBJ::save_batch(&objs).await.unwrap();
+
+        // Reads above and below the threshold come back the same
+        assert_eq!(BulkOBJ::get().await.unwrap(), objs);
+        assert_eq!(BulkOBJ::get_limit(2).await.unwrap(), objs[..2]);
+        assert_eq!(BulkOBJ::get_ordered(DOC_ID_FIELD, Order::Desc).await.unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_save_stream() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct FeedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(FeedOBJ, "memory_feed");
+
+        // More than two full batches, so several commit at once and the last one is partial
+        let feed = futures::stream::iter((0..1203).map(|i| FeedOBJ { key: format!("obj{:04}", i) }));
+        assert_eq!(FeedOBJ::save_stream(feed, 2).await.unwrap(), 1203);
+        assert_eq!(FeedOBJ::count().await.unwrap(), 1203);
+        assert_eq!(FeedOBJ::get_by_id(&"obj1202".to_string()).await.unwrap(), Some(FeedOBJ { key: "obj1202".to_string() }));
+
+        assert_eq!(FeedOBJ::save_stream(futures::stream::empty(), 0).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct DryOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(DryOBJ, "memory_dry", { dry_run: true });
+
+        // Saving isn't affected, only deleting
+        let objs: Vec<_> = (0..3).map(|i| DryOBJ { key: format!("obj{}", i), rank: i }).collect();
+        DryOBJ::save_batch(&objs).await.unwrap();
+
+        objs[0].rm().await.unwrap();
+        assert!(objs[0].rm_counted().await.unwrap());
+        DryOBJ::rm_by_id(&objs[1].key).await.unwrap();
+        DryOBJ::rm_by_id_strict(&objs[1].key).await.unwrap();
+        assert!(matches!(DryOBJ::rm_by_id_strict(&"zzz".to_string()).await, Err(CloudSyncError::NotFound { .. })));
+        let missing = DryOBJ { key: "zzz".to_string(), rank: 0 };
+        assert!(!missing.rm_counted().await.unwrap());
+        DryOBJ::rm_batch(&[objs[0].key.clone(), objs[2].key.clone()]).await.unwrap();
+        assert_eq!(DryOBJ::rm_where("rank", FilterOp::Gte, 1).await.unwrap(), 2);
+
+        assert_eq!(DryOBJ::get().await.unwrap(), objs);
+    }
+
+    #[tokio::test]
+    async fn test_patch() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct Settings {
+            theme: String,
+            language: String,
+        }
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ProfileOBJ {
+            key: String,
+            name: String,
+            settings: Settings,
+        }
+
+        memory_fixture!(ProfileOBJ, "memory_patched");
+
+        let settings = Settings { theme: "light".to_string(), language: "en".to_string() };
+        let obj = ProfileOBJ { key: "aaa".to_string(), name: "alice".to_string(), settings };
+        obj.save().await.unwrap();
+
+        let fields = HashMap::from([
+            ("name".to_string(), serde_json::json!("alicia")),
+            ("settings.theme".to_string(), serde_json::json!("dark")),
+        ]);
+        ProfileOBJ::patch(&obj.key, fields.clone()).await.unwrap();
+        let patched = ProfileOBJ::get_by_id(&obj.key).await.unwrap().unwrap();
+        assert_eq!((patched.name.as_str(), patched.settings.theme.as_str()), ("alicia", "dark"));
+        // The sibling of a nested path is left alone
+        assert_eq!(patched.settings.language, "en");
+
+        assert!(matches!(ProfileOBJ::patch(&"zzz".to_string(), fields).await, Err(CloudSyncError::NotFound { .. })));
+        let bad = HashMap::from([("settings.".to_string(), serde_json::json!("x"))]);
+        assert!(matches!(ProfileOBJ::patch(&obj.key, bad).await, Err(CloudSyncError::InvalidField(_))));
+    }
+
+    #[tokio::test]
+    async fn test_collection_prefix() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct AcmeOBJ {
+            key: String,
+        }
+
+        memory_fixture!(AcmeOBJ, "memory_tenant", { collection_prefix: Some("acme_".to_string()) });
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct GlobexOBJ {
+            key: String,
+        }
+
+        impl CloudSync<String, InMemoryBackend> for GlobexOBJ {
+            fn config() -> CLConfig {
+                CLConfig { collection_prefix: Some("globex_".to_string()), ..AcmeOBJ::config() }
+            }
+        }
+
+        impl Unique<String> for GlobexOBJ {
+            fn uuid(&self) -> String {
+                String::from(&self.key)
+            }
+        }
+
+        let obj = AcmeOBJ { key: "aaa".to_string() };
+        obj.save().await.unwrap();
+        assert_eq!(obj.collection(), "acme_memory_tenant");
+        assert_eq!(AcmeOBJ::get().await.unwrap(), vec![obj.clone()]);
+
+        // The same collection name under another prefix sees none of it
+        assert!(GlobexOBJ::get().await.unwrap().is_empty());
+        assert!(!GlobexOBJ::exists(&"aaa".to_string()).await.unwrap());
+        assert_eq!(GlobexOBJ::rm_where("key", FilterOp::Eq, "aaa").await.unwrap(), 0);
+        assert!(AcmeOBJ::exists(&"aaa".to_string()).await.unwrap());
+
+        let ids = list_collection_ids::<InMemoryBackend>(&AcmeOBJ::config()).await.unwrap();
+        assert!(ids.contains(&"acme_memory_tenant".to_string()));
+        assert!(!ids.contains(&"memory_tenant".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_document_too_large() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct BlobOBJ {
+            key: String,
+            body: String,
+        }
+
+        memory_fixture!(BlobOBJ, "memory_blobs");
+
+        let small = BlobOBJ { key: "small".to_string(), body: "x".repeat(1000) };
+        let big = BlobOBJ { key: "big".to_string(), body: "x".repeat(MAX_DOCUMENT_BYTES) };
+        small.save().await.unwrap();
+        match big.save().await {
+            Err(CloudSyncError::DocumentTooLarge { id, bytes }) => assert_eq!((id.as_str(), bytes > MAX_DOCUMENT_BYTES), ("big", true)),
+            other => panic!("expected DocumentTooLarge, got {:?}", other),
+        }
+        assert!(matches!(BlobOBJ::save_batch(&[small.clone(), big]).await, Err(CloudSyncError::Batch { committed: 0, .. })));
+        assert!(!BlobOBJ::exists(&"big".to_string()).await.unwrap());
+
+        // Name, fields and overhead are counted the way firestore does
+        let cfg = BlobOBJ::config();
+        let doc = serialize_doc(&cfg, "projects/p/databases/(default)/documents/memory_blobs/small", &small).unwrap();
+        assert_eq!(doc_size(&doc), (13 + 6 + 16) + (4 + 6) + (5 + 1001) + 32);
+        let strict = CLConfig { max_document_bytes: 1000, ..cfg };
+        assert!(matches!(serialize_doc(&strict, &doc.name, &small), Err(CloudSyncError::DocumentTooLarge { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_collection_handle() {
+        // A handle injected with its own config reads and writes there, leaving the type's own collection alone
+        let handle: Collection<MemOBJ, String, InMemoryBackend> = Collection::new(CLConfig { collection: "memory_handle".to_string(), ..MemOBJ::config() });
+        let obj = mem_obj("handled", 7);
+        handle.save(&obj).await.unwrap();
+        assert_eq!(handle.get().await.unwrap(), vec![obj.clone()]);
+        assert_eq!(handle.get_by_id(&obj.key).await.unwrap(), Some(obj.clone()));
+        assert!(handle.exists(&obj.key).await.unwrap());
+        assert_eq!(handle.count().await.unwrap(), 1);
+        assert!(!MemOBJ::exists(&obj.key).await.unwrap());
+
+        let cloned = handle.clone();
+        cloned.rm(&obj).await.unwrap();
+        assert!(!handle.exists(&obj.key).await.unwrap());
+
+        assert_eq!(MemOBJ::collection_handle().config().collection, "memory");
+    }
+
+    #[cfg(feature = "uuid")]
+    #[tokio::test]
+    async fn test_uuid_key() {
+        use uuid::Uuid;
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct UuidOBJ {
+            id: Uuid,
+            name: String,
+        }
+
+        impl CloudSync<Uuid, InMemoryBackend> for UuidOBJ {
+            fn config() -> CLConfig {
+                CLConfig { collection: "memory_uuid".to_string(), ..MemOBJ::config() }
+            }
+        }
+
+        impl Unique<Uuid> for UuidOBJ {
+            fn uuid(&self) -> Uuid {
+                self.id
+            }
+        }
+
+        impl AssignUuid for UuidOBJ {
+            fn set_uuid(&mut self, uuid: Uuid) {
+                self.id = uuid;
+            }
+        }
+
+        let mut obj = UuidOBJ { id: Uuid::nil(), name: "fresh".to_string() };
+        obj.save_with_new_uuid().await.unwrap();
+        assert!(!obj.id.is_nil());
+        let assigned = obj.id;
+        // An object that already has a uuid keeps it
+        obj.save_with_new_uuid().await.unwrap();
+        assert_eq!(obj.id, assigned);
+
+        // The document id is the uuid's string form, and parses back to the same uuid
+        let (read, meta) = UuidOBJ::get_with_meta(&assigned).await.unwrap().unwrap();
+        assert_eq!(read, obj);
+        assert_eq!(meta.id, assigned.to_string());
+        assert_eq!(Uuid::parse_str(&meta.id).unwrap(), assigned);
+        assert_eq!(UuidOBJ::hash().await.unwrap().get(&assigned), Some(&obj));
+    }
+
+    #[tokio::test]
+    async fn test_get_as() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ViewedOBJ {
+            key: String,
+            data: String,
+            rank: i32,
+        }
+
+        memory_fixture!(ViewedOBJ, "memory_ranked");
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct RankView {
+            rank: i32,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct LabelView {
+            rank: String,
+        }
+
+        ViewedOBJ { key: "aaa".to_string(), data: "data".to_string(), rank: 1 }.save().await.unwrap();
+        ViewedOBJ { key: "bbb".to_string(), data: "data".to_string(), rank: 2 }.save().await.unwrap();
+
+        let views: Vec<RankView> = ViewedOBJ::get_as().await.unwrap();
+        assert_eq!(views, vec![RankView { rank: 1 }, RankView { rank: 2 }]);
+        // A read type the documents don't fit fails the same way `get()` would
+        assert!(ViewedOBJ::get_as::<LabelView>().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_copy_and_move() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct TransferOBJ {
+            key: String,
+            data: String,
+            rank: i32,
+        }
+
+        memory_fixture!(TransferOBJ, "memory_transfer");
+
+        let archive: Collection<TransferOBJ, String, InMemoryBackend> =
+            Collection::new(CLConfig { collection: "memory_archive".to_string(), ..TransferOBJ::config() });
+        let a = TransferOBJ { key: "aaa".to_string(), data: "data".to_string(), rank: 1 };
+        let b = TransferOBJ { key: "bbb".to_string(), data: "data".to_string(), rank: 2 };
+        a.save().await.unwrap();
+        b.save().await.unwrap();
+
+        a.copy_to("memory_archive").await.unwrap();
+        assert_eq!(archive.get_by_id(&a.key).await.unwrap(), Some(a.clone()));
+        assert!(TransferOBJ::exists(&a.key).await.unwrap());
+
+        b.move_to("memory_archive").await.unwrap();
+        assert_eq!(archive.get_by_id(&b.key).await.unwrap(), Some(b.clone()));
+        assert!(!TransferOBJ::exists(&b.key).await.unwrap());
+
+        // Moving into its own collection would delete the object it just wrote
+        assert!(matches!(a.move_to("memory_transfer").await, Err(CloudSyncError::Unsupported(_))));
+        assert!(TransferOBJ::exists(&a.key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_get_id_range() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct RangedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(RangedOBJ, "memory_ranged");
+
+        for key in ["apple", "banana", "cherry", "mango", "melon", "plum"] {
+            RangedOBJ { key: key.to_string() }.save().await.unwrap();
+        }
+        let keys = |objects: Vec<RangedOBJ>| objects.into_iter().map(|obj| obj.key).collect::<Vec<_>>();
+
+        assert_eq!(keys(RangedOBJ::get_id_range("banana", "mango").await.unwrap()), vec!["banana", "cherry"]);
+        // Adjoining ranges cover everything exactly once
+        assert_eq!(keys(RangedOBJ::get_id_range("", "m").await.unwrap()), vec!["apple", "banana", "cherry"]);
+        assert_eq!(keys(RangedOBJ::get_id_range("m", "").await.unwrap()), vec!["mango", "melon", "plum"]);
+        assert!(RangedOBJ::get_id_range("x", "z").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_doc_id() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct BatchOBJ {
+            key: String,
+        }
+
+        memory_fixture!(BatchOBJ, "memory_invalid");
+
+        fn invalid<R>(result: Result<R, CloudSyncError>) -> bool {
+            matches!(result, Err(CloudSyncError::InvalidDocumentId(_)))
+        }
+        for id in ["", "a/b", ".", "..", "__reserved__"] {
+            let obj = mem_obj(id, 1);
+            assert!(matches!(obj.save().await, Err(CloudSyncError::InvalidDocumentId(bad)) if bad == id), "saved `{}`", id);
+            assert!(matches!(obj.rm().await, Err(CloudSyncError::InvalidDocumentId(_))));
+            assert!(matches!(MemOBJ::get_by_id(&id.to_string()).await, Err(CloudSyncError::InvalidDocumentId(_))));
+            assert!(matches!(MemOBJ::rm_by_id(&id.to_string()).await, Err(CloudSyncError::InvalidDocumentId(_))));
+            assert!(matches!(obj.rm_counted().await, Err(CloudSyncError::InvalidDocumentId(_))));
+            let id = id.to_string();
+            assert!(invalid(MemOBJ::rm_by_id_strict(&id).await));
+            assert!(invalid(obj.soft_rm().await));
+            assert!(invalid(obj.restore().await));
+            assert!(invalid(obj.move_to("memory_invalid_moved").await));
+            assert!(invalid(MemOBJ::increment(&id, "rank", 1).await));
+            assert!(invalid(MemOBJ::array_add(&id, "tags", &["a"]).await));
+            assert!(invalid(MemOBJ::array_remove(&id, "tags", &["a"]).await));
+            assert!(invalid(MemOBJ::get_with_meta(&id).await));
+            assert!(invalid(MemOBJ::get_many_by_ids(std::slice::from_ref(&id)).await));
+            assert!(invalid(MemOBJ::exists(&id).await));
+            assert!(invalid(MemOBJ::get_page(10, Some(id.clone())).await));
+            assert!(invalid(MemOBJ::get_page_before(10, id.clone()).await));
+        }
+        assert!(matches!(mem_obj(&"x".repeat(1501), 1).save().await, Err(CloudSyncError::InvalidDocumentId(_))));
+
+        BatchOBJ { key: "aaa".to_string() }.save().await.unwrap();
+        // One bad id fails the whole batch before anything is removed
+        let ids = ["aaa".to_string(), "a/b".to_string()];
+        assert!(matches!(BatchOBJ::rm_batch(&ids).await, Err(CloudSyncError::InvalidDocumentId(bad)) if bad == "a/b"));
+        assert!(BatchOBJ::exists(&"aaa".to_string()).await.unwrap());
+        // Dots and underscores are fine as part of a longer id
+        assert!(check_doc_id("...").is_ok());
+        assert!(check_doc_id("__init").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_scan_parallel() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ScannedOBJ {
+            key: String,
+        }
+
+        memory_fixture!(ScannedOBJ, "memory_scanned");
+
+        assert_eq!(shard_bounds(1), Vec::<String>::new());
+        assert_eq!(shard_bounds(2), vec!["V0".to_string()]);
+        assert_eq!(shard_bounds(usize::MAX).len(), 62 * 62 - 1);
+
+        // Ids from all over the id space, including some outside the auto-id characters
+        let mut keys: Vec<String> = (0..200).map(|i| format!("{}{}", AUTO_ID_CHARS[i % 62] as char, i)).collect();
+        keys.extend(["!first".to_string(), "~last".to_string()]);
+        for key in &keys {
+            ScannedOBJ { key: key.clone() }.save().await.unwrap();
+        }
+
+        let seen = std::sync::Mutex::new(vec![]);
+        ScannedOBJ::scan_parallel(7, 3, |obj| {
+            seen.lock().unwrap().push(obj.key);
+            async { Ok(()) }
+        }).await.unwrap();
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        keys.sort();
+        assert_eq!(seen, keys);
+
+        let failed = ScannedOBJ::scan_parallel(4, 4, |obj| async move {
+            match obj.key.as_str() {
+                "~last" => Err(CloudSyncError::InvalidField("key".to_string())),
+                _ => Ok(()),
+            }
+        }).await;
+        assert!(matches!(failed, Err(CloudSyncError::InvalidField(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_group() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct LineItemOBJ {
+            key: String,
+        }
+
+        memory_fixture!(LineItemOBJ, "line_items", { parent_path: Some("carts/alice".to_string()) });
+
+        let under = |parent_path: &str| -> Collection<LineItemOBJ, String, InMemoryBackend> {
+            Collection::new(CLConfig { parent_path: Some(parent_path.to_string()), ..LineItemOBJ::config() })
+        };
+        LineItemOBJ { key: "a1".to_string() }.save().await.unwrap();
+        under("carts/bob").save(&LineItemOBJ { key: "b1".to_string() }).await.unwrap();
+        under("stores/s1/carts/carol").save(&LineItemOBJ { key: "c1".to_string() }).await.unwrap();
+        // Only collections with exactly this id are part of the group
+        let other: Collection<LineItemOBJ, String, InMemoryBackend> =
+            Collection::new(CLConfig { collection: "line_items_old".to_string(), ..LineItemOBJ::config() });
+        other.save(&LineItemOBJ { key: "old".to_string() }).await.unwrap();
+
+        assert_eq!(LineItemOBJ::get().await.unwrap(), vec![LineItemOBJ { key: "a1".to_string() }]);
+        let mut keys: Vec<String> = LineItemOBJ::get_group().await.unwrap().into_iter().map(|obj| obj.key).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["a1", "b1", "c1"]);
+    }
+
+    #[tokio::test]
+    async fn test_get_lenient() {
+        let cfg = CLConfig { collection: "memory_lenient".to_string(), ..MemOBJ::config() };
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct LenientOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(LenientOBJ, "memory_lenient");
+
+        #[derive(Serialize)]
+        struct OldSchema {
+            key: String,
+            rank: String,
+        }
+
+        LenientOBJ { key: "aaa".to_string(), rank: 1 }.save().await.unwrap();
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        let old = OldSchema { key: "bbb".to_string(), rank: "first".to_string() };
+        backend.commit(vec![set_write(&cfg, &parent, &cfg.collection, "bbb", &old).unwrap()]).await.unwrap();
+
+        assert!(LenientOBJ::get().await.is_err());
+        let (objects, failures) = LenientOBJ::get_lenient().await.unwrap();
+        assert_eq!(objects, vec![LenientOBJ { key: "aaa".to_string(), rank: 1 }]);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].id, "bbb");
+        assert!(matches!(failures[0].error, CloudSyncError::Serialization(_)));
+    }
+
+    #[tokio::test]
+    async fn test_migrate() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ProfileOBJ {
+            key: String,
+            name: String,
+            #[serde(default)]
+            display_name: Option<String>,
+        }
+
+        memory_fixture!(ProfileOBJ, "memory_profiles");
+
+        let profile = |key: &str, name: &str| ProfileOBJ { key: key.to_string(), name: name.to_string(), display_name: None };
+        ProfileOBJ::save_batch(&[profile("aaa", "alice"), profile("bbb", "bob")]).await.unwrap();
+
+        let migrated = ProfileOBJ::migrate(|obj| ProfileOBJ { display_name: Some(obj.name.to_uppercase()), ..obj }).await.unwrap();
+        assert_eq!(migrated, 2);
+        let names: Vec<_> = ProfileOBJ::get().await.unwrap().into_iter().map(|obj| obj.display_name).collect();
+        assert_eq!(names, vec![Some("ALICE".to_string()), Some("BOB".to_string())]);
+
+        let migrated = ProfileOBJ::migrate_async(|obj| async move { ProfileOBJ { name: format!("{}!", obj.name), ..obj } }).await.unwrap();
+        assert_eq!(migrated, 2);
+        assert_eq!(ProfileOBJ::get_by_id(&"bbb".to_string()).await.unwrap().unwrap().name, "bob!");
+    }
+
+    #[tokio::test]
+    async fn test_save_merge() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct Settings {
+            theme: String,
+            #[serde(default)]
+            language: Option<String>,
+        }
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct AccountView {
+            key: String,
+            email: String,
+            settings: Settings,
+        }
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct BillingView {
+            key: String,
+            plan: String,
+        }
+
+        memory_fixture!(AccountView, "memory_views");
+
+        memory_fixture!(BillingView, "memory_views");
+
+        let id = "aaa".to_string();
+        let account = AccountView {
+            key: id.clone(),
+            email: "a@example.com".to_string(),
+            settings: Settings { theme: "dark".to_string(), language: Some("en".to_string()) },
+        };
+        account.save().await.unwrap();
+        let billing = BillingView { key: id.clone(), plan: "pro".to_string() };
+        billing.save_merge().await.unwrap();
+
+        assert_eq!(AccountView::get_by_id(&id).await.unwrap(), Some(account.clone()));
+        assert_eq!(BillingView::get_by_id(&id).await.unwrap(), Some(billing.clone()));
+
+        // A plain save replaces the whole document, dropping the other view's fields
+        billing.save().await.unwrap();
+        assert!(AccountView::get_by_id(&id).await.is_err());
+
+        let mut paths = merge_paths(&FirestoreDb::serialize_to_doc("", &account).unwrap().fields, "");
+        paths.sort();
+        assert_eq!(paths, vec!["email", "key", "settings.language", "settings.theme"]);
+    }
+
+    #[tokio::test]
+    async fn test_save_with_id() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct LegacyOBJ {
+            key: String,
+            rank: i32,
+        }
+
+        memory_fixture!(LegacyOBJ, "memory_legacy");
+
+        let obj = LegacyOBJ { key: "aaa".to_string(), rank: 1 };
+        obj.save_with_id("legacy-0001").await.unwrap();
+        assert_eq!(LegacyOBJ::get_by_id(&"legacy-0001".to_string()).await.unwrap(), Some(obj.clone()));
+        assert_eq!(LegacyOBJ::get_by_id(&"aaa".to_string()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_server_timestamp() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct Audit {
+            reviewed_at: ServerTimestamp,
+        }
+
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct EventOBJ {
+            key: String,
+            created_at: ServerTimestamp,
+            audit: Audit,
+        }
+
+        memory_fixture!(EventOBJ, "memory_events");
+
+        let before = Utc::now();
+        let event = EventOBJ { key: "aaa".to_string(), created_at: ServerTimestamp::Pending, audit: Audit { reviewed_at: ServerTimestamp::Pending } };
+        let saved = event.save_and_read().await.unwrap();
+        let created = saved.created_at.time().unwrap();
+        assert!(created >= before && created <= Utc::now());
+        assert!(saved.audit.reviewed_at.time().is_some());
+
+        // An assigned time is written back as it is rather than replaced
+        let older = ServerTimestamp::At(from_timestamp(to_timestamp(before - chrono::Duration::days(1))).unwrap());
+        let reviewed = EventOBJ { audit: Audit { reviewed_at: older }, ..saved.clone() };
+        reviewed.save().await.unwrap();
+        assert_eq!(EventOBJ::get_by_id(&"aaa".to_string()).await.unwrap(), Some(reviewed));
+
+        let write = set_write(&EventOBJ::config(), "", "memory_events", "bbb", &event).unwrap();
+        let mut paths: Vec<String> = write.update_transforms.into_iter().map(|transform| transform.field_path).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["audit.reviewed_at", "created_at"]);
+    }
+
+    #[tokio::test]
+    async fn test_write_nulls() {
+        #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+        struct ContactOBJ {
+            key: String,
+            #[serde(default)]
+            phone: Option<String>,
+        }
+
+        memory_fixture!(ContactOBJ, "memory_contacts", { write_nulls: false });
+
+        let cfg = ContactOBJ::config();
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        let nulls_cfg = CLConfig { write_nulls: true, ..cfg.clone() };
+        let bob = ContactOBJ { key: "bbb".to_string(), phone: None };
+        backend.commit(vec![set_write(&nulls_cfg, &parent, &cfg.collection, "bbb", &bob).unwrap()]).await.unwrap();
+        let stored = backend.get_doc(&parent, &cfg.collection, "bbb").await.unwrap().unwrap();
+        assert_eq!(stored.fields["phone"].value_type, Some(value::ValueType::NullValue(0)));
+
+        let alice = ContactOBJ { key: "aaa".to_string(), phone: None };
+        alice.save().await.unwrap();
+        let stored = backend.get_doc(&parent, &cfg.collection, "aaa").await.unwrap().unwrap();
+        assert!(!stored.fields.contains_key("phone"));
+        assert_eq!(ContactOBJ::get_by_id(&"aaa".to_string()).await.unwrap(), Some(alice.clone()));
+
+        // Only the explicit null matches a `phone == null` query
+        let nulls = ContactOBJ::get_where("phone", None::<String>).await.unwrap();
+        assert_eq!(nulls, vec![bob]);
+
+        // A left out field isn't part of the merge, so the stored phone survives
+        ContactOBJ { phone: Some("555".to_string()), ..alice.clone() }.save().await.unwrap();
+        alice.save_merge().await.unwrap();
+        assert_eq!(ContactOBJ::get_by_id(&"aaa".to_string()).await.unwrap().unwrap().phone, Some("555".to_string()));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn test_test_collection() {
+        let guard = TestCollection::<InMemoryBackend>::new(MemOBJ::config()).await.unwrap();
+        let cfg = guard.config().clone();
+        assert!(guard.name().starts_with("memory_test_"));
+        assert_ne!(guard.name(), TestCollection::<InMemoryBackend>::new(MemOBJ::config()).await.unwrap().name());
+
+        guard.seed(&[mem_obj("aaa", 1), mem_obj("bbb", 2)]).await.unwrap();
+        let mut found: Vec<MemOBJ> = guard.get_all().await.unwrap();
+        found.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(found, vec![mem_obj("aaa", 1), mem_obj("bbb", 2)]);
+
+        drop(guard);
+        let backend = InMemoryBackend::connect(&cfg).await.unwrap();
+        let parent = cfg.parent(backend.documents_path());
+        assert!(backend.query(query_params(&parent, &cfg.collection)).await.unwrap().is_empty());
+
+        let guard = TestCollection::<InMemoryBackend>::new(MemOBJ::config()).await.unwrap();
+        guard.seed(&[mem_obj("ccc", 3)]).await.unwrap();
+        assert_eq!(guard.teardown().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_named_database() {
+        let default_cfg = CLConfig { collection: "memory_databases".to_string(), ..MemOBJ::config() };
+        let dev_cfg = CLConfig { database_id: Some("dev".to_string()), ..default_cfg.clone() };
+        let default_db = InMemoryBackend::connect(&default_cfg).await.unwrap();
+        let dev_db = InMemoryBackend::connect(&dev_cfg).await.unwrap();
+        assert_eq!(dev_db.documents_path(), "projects/cloudsync-memory/databases/dev/documents");
+
+        let parent = dev_cfg.parent(dev_db.documents_path());
+        dev_db.commit(vec![set_write(&dev_cfg, &parent, &dev_cfg.collection, "aaa", &mem_obj("aaa", 1)).unwrap()]).await.unwrap();
+        assert!(dev_db.get_doc(&parent, &dev_cfg.collection, "aaa").await.unwrap().is_some());
+        let default_parent = default_cfg.parent(default_db.documents_path());
+        assert!(default_db.get_doc(&default_parent, &default_cfg.collection, "aaa").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_credentials_file() {
+        // Against the emulator no credentials are read at all
+        if std::env::var("FIRESTORE_EMULATOR_HOST").is_ok() {
+            return;
+        }
+        let cfg = |path: PathBuf| CLConfig {
+            project_id: "cloudsync-credentials".to_string(),
+            credentials: CredentialSource::File(path),
+            collection: "testing".to_string(),
+            ..Default::default()
+        };
+        let missing = std::env::temp_dir().join(format!("cloudsync-missing-{}.json", std::process::id()));
+        assert!(matches!(FirestoreBackend::connect(&cfg(missing)).await, Err(CloudSyncError::CredentialsNotFound(_))));
+
+        // An unusable file is read and handed to the sdk, which rejects it before anything goes over the network
+        let garbage = std::env::temp_dir().join(format!("cloudsync-garbage-{}.json", std::process::id()));
+        std::fs::write(&garbage, "not credentials").unwrap();
+        let result = FirestoreBackend::connect(&cfg(garbage.clone())).await;
+        std::fs::remove_file(&garbage).unwrap();
+        assert!(matches!(result, Err(CloudSyncError::Connection(_))));
+    }
+}
@@ -0,0 +1,561 @@
+use crate::{Backend, CLConfig, CloudSyncError, DocChange, RetryPolicy, DOC_ID_FIELD};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use firestore::errors::{FirestoreDataConflictError, FirestoreDataNotFoundError, FirestoreDatabaseError, FirestoreError, FirestoreErrorPublicGenericDetails};
+use firestore::timestamp_utils::to_timestamp;
+use firestore::FirestoreQueryParams;
+use futures::channel::mpsc;
+use futures::stream::{self, BoxStream, StreamExt};
+use gcloud_sdk::google::firestore::v1::structured_query::{field_filter, filter, unary_filter, composite_filter, Direction, Filter};
+use gcloud_sdk::google::firestore::v1::document_transform::field_transform;
+use gcloud_sdk::google::firestore::v1::{precondition, value, write, ArrayValue, Cursor, Document, MapValue, StructuredQuery, Value, Write};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Every in-memory store, keyed on project_id, so all objects configured with the same project share their documents
+static STORES: OnceLock<Mutex<HashMap<String, Arc<Mutex<Store>>>>> = OnceLock::new();
+
+/// A backend that keeps documents in memory instead of firestore, for unit testing code that uses `CloudSync`
+/// Documents go through the same serializer as firestore and writes, preconditions and queries follow firestore's rules
+/// (saves are upserts, reading or deleting a missing document isn't an error), so tests written against it behave like production
+///
+/// The documents live as long as the process and are shared by every object whose config has the same project_id,
+/// so give each test its own collection or call `clear()` to keep tests apart
+#[derive(Clone)]
+pub struct InMemoryBackend {
+    documents_path: String,
+    store: Arc<Mutex<Store>>,
+}
+
+/// The documents of one project, keyed on their full name
+#[derive(Default)]
+struct Store {
+    docs: BTreeMap<String, Document>,
+    last_write: Option<DateTime<Utc>>,
+    listeners: Vec<Subscriber>,
+}
+
+impl InMemoryBackend {
+    /// Remove every document stored for this backend's project
+    pub fn clear(&self) {
+        self.store.lock().unwrap().docs.clear();
+    }
+}
+
+#[async_trait]
+impl Backend for InMemoryBackend {
+    async fn connect(cfg: &CLConfig) -> Result<Self, CloudSyncError> {
+        if cfg.read_time.is_some() {
+            return Err(CloudSyncError::Unsupported("the in-memory backend keeps no history to read at a read_time".to_string()));
+        }
+        let store = STORES.get_or_init(Default::default).lock().unwrap()
+            .entry(cfg.project_id.clone())
+            .or_default()
+            .clone();
+        Ok(InMemoryBackend {
+            documents_path: format!("projects/{}/databases/{}/documents", cfg.project_id, cfg.database_id.as_deref().unwrap_or("(default)")),
+            store,
+        })
+    }
+
+    fn documents_path(&self) -> &str {
+        &self.documents_path
+    }
+
+    async fn commit(&self, writes: Vec<Write>) -> Result<(), CloudSyncError> {
+        let mut store = self.store.lock().unwrap();
+        // Like firestore, every write in a commit gets the same update time, which is later than any earlier write
+        let now = Utc::now().duration_trunc(Duration::microseconds(1)).unwrap();
+        let now = match store.last_write {
+            Some(last) if last >= now => last + Duration::microseconds(1),
+            _ => now,
+        };
+        // Apply to a copy so a failing write leaves the store untouched
+        let mut docs = store.docs.clone();
+        let mut names = vec![];
+        for write in writes {
+            names.push(apply_write(&mut docs, write, now)?);
+        }
+        let before = std::mem::replace(&mut store.docs, docs);
+        store.last_write = Some(now);
+        store.notify(&before, &names);
+        Ok(())
+    }
+
+    async fn get_doc(&self, parent: &str, collection: &str, id: &str) -> Result<Option<Document>, CloudSyncError> {
+        let name = format!("{}/{}/{}", parent, collection, id);
+        Ok(self.store.lock().unwrap().docs.get(&name).cloned())
+    }
+
+    async fn get_docs(&self, parent: &str, collection: &str, ids: &[String]) -> Result<Vec<Document>, CloudSyncError> {
+        let store = self.store.lock().unwrap();
+        Ok(ids.iter()
+            .filter_map(|id| store.docs.get(&format!("{}/{}/{}", parent, collection, id)).cloned())
+            .collect())
+    }
+
+    async fn query(&self, params: FirestoreQueryParams) -> Result<Vec<Document>, CloudSyncError> {
+        crate::trace::log_query(&params);
+        let parent = params.parent.clone().unwrap_or_else(|| self.documents_path.clone());
+        let query = StructuredQuery::try_from(params)?;
+        let mut docs: Vec<Document> = self.store.lock().unwrap().docs.values()
+            .filter(|doc| query_matches(doc, &parent, &query))
+            .cloned()
+            .collect();
+
+        // Firestore always breaks ties on the document name, in the direction of the last explicit ordering
+        let mut order: Vec<(String, Direction)> = query.order_by.iter()
+            .map(|order| (order.field.as_ref().map(|field| field.field_path.clone()).unwrap_or_default(), order.direction()))
+            .collect();
+        if !order.iter().any(|(field, _)| field == DOC_ID_FIELD) {
+            let direction = order.last().map_or(Direction::Ascending, |(_, direction)| *direction);
+            order.push((DOC_ID_FIELD.to_string(), direction));
+        }
+        // Documents missing an ordered field are left out of the results
+        docs.retain(|doc| order.iter().all(|(field, _)| field_value(doc, field).is_some()));
+        docs.sort_by(|a, b| {
+            order.iter()
+                .map(|(field, direction)| directed(cmp_values(&field_value(a, field).unwrap(), &field_value(b, field).unwrap()), *direction))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        if let Some(start) = &query.start_at {
+            docs.retain(|doc| match cmp_to_cursor(doc, &order, start) {
+                Ordering::Greater => true,
+                Ordering::Equal => start.before,
+                Ordering::Less => false,
+            });
+        }
+        if let Some(end) = &query.end_at {
+            docs.retain(|doc| match cmp_to_cursor(doc, &order, end) {
+                Ordering::Less => true,
+                Ordering::Equal => !end.before,
+                Ordering::Greater => false,
+            });
+        }
+        let docs = docs.into_iter()
+            .skip(query.offset.max(0) as usize)
+            .take(query.limit.map_or(usize::MAX, |limit| limit.max(0) as usize));
+
+        Ok(match &query.select {
+            Some(select) => docs.map(|doc| project(doc, select.fields.iter().map(|field| field.field_path.as_str()))).collect(),
+            None => docs.collect(),
+        })
+    }
+
+    async fn query_stream(&self, params: FirestoreQueryParams) -> Result<BoxStream<'static, Result<Document, CloudSyncError>>, CloudSyncError> {
+        let docs = self.query(params).await?;
+        Ok(stream::iter(docs.into_iter().map(Ok)).boxed())
+    }
+
+    async fn count(&self, params: FirestoreQueryParams) -> Result<usize, CloudSyncError> {
+        Ok(self.query(params).await?.len())
+    }
+
+    async fn listen(&self, params: FirestoreQueryParams, _retry: RetryPolicy) -> Result<BoxStream<'static, Result<DocChange, CloudSyncError>>, CloudSyncError> {
+        crate::trace::log_query(&params);
+        let parent = params.parent.clone().unwrap_or_else(|| self.documents_path.clone());
+        let query = StructuredQuery::try_from(params)?;
+        let (sender, receiver) = mpsc::unbounded();
+        let mut store = self.store.lock().unwrap();
+        // Registering under the same lock as the snapshot means no commit can slip in between the two
+        for doc in store.docs.values().filter(|doc| query_matches(doc, &parent, &query)) {
+            let _ = sender.unbounded_send(Ok(DocChange::Changed(doc.clone())));
+        }
+        store.listeners.push(Subscriber { parent, query, sender });
+        Ok(receiver.boxed())
+    }
+
+    async fn list_collection_ids(&self, parent: &str) -> Result<Vec<String>, CloudSyncError> {
+        // Like firestore, a collection shows up as long as some document sits anywhere below it
+        let prefix = format!("{}/", parent);
+        let ids: BTreeSet<String> = self.store.lock().unwrap().docs.keys()
+            .filter_map(|name| name.strip_prefix(&prefix)?.split('/').next().map(String::from))
+            .collect();
+        Ok(ids.into_iter().collect())
+    }
+}
+
+/// A listener waiting on changes to the documents its query matches
+struct Subscriber {
+    parent: String,
+    query: StructuredQuery,
+    sender: mpsc::UnboundedSender<Result<DocChange, CloudSyncError>>,
+}
+
+impl Store {
+    /// Tell every listener about the documents that changed in a commit, dropping listeners that have gone away
+    fn notify(&mut self, before: &BTreeMap<String, Document>, names: &[String]) {
+        let docs = &self.docs;
+        self.listeners.retain(|listener| {
+            names.iter().all(|name| {
+                let was = before.get(name).filter(|doc| query_matches(doc, &listener.parent, &listener.query));
+                let now = docs.get(name).filter(|doc| query_matches(doc, &listener.parent, &listener.query));
+                let change = match (was, now) {
+                    (_, Some(doc)) => DocChange::Changed(doc.clone()),
+                    (Some(_), None) => DocChange::Removed(name.clone()),
+                    (None, None) => return true,
+                };
+                listener.sender.unbounded_send(Ok(change)).is_ok()
+            })
+        });
+    }
+}
+
+/// Apply a single write to the documents, checking its precondition first, and return the name of the document it wrote
+fn apply_write(docs: &mut BTreeMap<String, Document>, write: Write, now: DateTime<Utc>) -> Result<String, CloudSyncError> {
+    let name = match &write.operation {
+        Some(write::Operation::Update(doc)) => doc.name.clone(),
+        Some(write::Operation::Delete(name)) => name.clone(),
+        _ => return Err(CloudSyncError::Unsupported("the in-memory backend only supports update and delete writes".to_string())),
+    };
+    let existing = docs.get(&name);
+    match write.current_document.and_then(|precondition| precondition.condition_type) {
+        Some(precondition::ConditionType::Exists(true)) if existing.is_none() => {
+            return Err(FirestoreError::DataNotFoundError(FirestoreDataNotFoundError::new(
+                FirestoreErrorPublicGenericDetails::new("NotFound".to_string()),
+                format!("no document to update: {}", name),
+            )).into());
+        }
+        Some(precondition::ConditionType::Exists(false)) if existing.is_some() => {
+            return Err(FirestoreError::DataConflictError(FirestoreDataConflictError::new(
+                FirestoreErrorPublicGenericDetails::new("AlreadyExists".to_string()),
+                format!("document already exists: {}", name),
+            )).into());
+        }
+        Some(precondition::ConditionType::UpdateTime(time))
+            if existing.and_then(|doc| doc.update_time.as_ref()) != Some(&time) => {
+            return Err(FirestoreError::DatabaseError(FirestoreDatabaseError::new(
+                FirestoreErrorPublicGenericDetails::new("FailedPrecondition".to_string()),
+                format!("the stored version of {} does not match the required update time", name),
+                false,
+            )).into());
+        }
+        _ => {}
+    }
+
+    match write.operation {
+        Some(write::Operation::Update(doc)) => {
+            let created = existing.and_then(|existing| existing.create_time.clone()).unwrap_or_else(|| to_timestamp(now));
+            let fields = match write.update_mask {
+                Some(mask) => {
+                    let mut fields = existing.map(|existing| existing.fields.clone()).unwrap_or_default();
+                    for path in mask.field_paths {
+                        let path = parse_path(&path);
+                        match get_path(&doc.fields, &path) {
+                            Some(value) => set_path(&mut fields, &path, value.clone()),
+                            None => remove_path(&mut fields, &path),
+                        }
+                    }
+                    fields
+                }
+                None => doc.fields,
+            };
+            let mut fields = fields;
+            for transform in write.update_transforms {
+                let path = parse_path(&transform.field_path);
+                let current = get_path(&fields, &path).cloned();
+                if let Some(transform) = transform.transform_type {
+                    set_path(&mut fields, &path, apply_transform(current, transform, now));
+                }
+            }
+            docs.insert(name.clone(), Document {
+                name: name.clone(),
+                fields,
+                create_time: Some(created),
+                update_time: Some(to_timestamp(now)),
+            });
+        }
+        _ => {
+            docs.remove(&name);
+        }
+    }
+    Ok(name)
+}
+
+/// The value a field transform leaves in a field that currently holds `current`
+fn apply_transform(current: Option<Value>, transform: field_transform::TransformType, now: DateTime<Utc>) -> Value {
+    use field_transform::TransformType;
+    let number = |value: &Option<Value>| match value.as_ref().and_then(|value| value.value_type.as_ref()) {
+        Some(value::ValueType::IntegerValue(_)) | Some(value::ValueType::DoubleValue(_)) => value.clone(),
+        _ => None,
+    };
+    let elements = |value: Option<Value>| match value.and_then(|value| value.value_type) {
+        Some(value::ValueType::ArrayValue(array)) => array.values,
+        _ => vec![],
+    };
+    let array = |values| Value { value_type: Some(value::ValueType::ArrayValue(ArrayValue { values })) };
+    match transform {
+        TransformType::SetToServerValue(_) => Value { value_type: Some(value::ValueType::TimestampValue(to_timestamp(now))) },
+        // Like firestore, a field that doesn't hold a number is treated as if it were missing and set to the operand
+        TransformType::Increment(delta) => match (number(&current).and_then(|value| value.value_type), &delta.value_type) {
+            (Some(value::ValueType::IntegerValue(a)), Some(value::ValueType::IntegerValue(b))) => {
+                Value { value_type: Some(value::ValueType::IntegerValue(a.saturating_add(*b))) }
+            }
+            (Some(current), Some(_)) => Value {
+                value_type: Some(value::ValueType::DoubleValue(as_double(&current) + as_double(delta.value_type.as_ref().unwrap()))),
+            },
+            _ => delta,
+        },
+        TransformType::Maximum(operand) => match number(&current) {
+            Some(current) if cmp_values(&current, &operand).is_ge() => current,
+            _ => operand,
+        },
+        TransformType::Minimum(operand) => match number(&current) {
+            Some(current) if cmp_values(&current, &operand).is_le() => current,
+            _ => operand,
+        },
+        TransformType::AppendMissingElements(values) => {
+            let mut existing = elements(current);
+            for value in values.values {
+                if !existing.iter().any(|existing| cmp_values(existing, &value).is_eq()) {
+                    existing.push(value);
+                }
+            }
+            array(existing)
+        }
+        TransformType::RemoveAllFromArray(values) => {
+            let mut existing = elements(current);
+            existing.retain(|existing| !values.values.iter().any(|value| cmp_values(existing, value).is_eq()));
+            array(existing)
+        }
+    }
+}
+
+fn as_double(value: &value::ValueType) -> f64 {
+    match value {
+        value::ValueType::IntegerValue(int) => *int as f64,
+        value::ValueType::DoubleValue(double) => *double,
+        _ => 0.0,
+    }
+}
+
+/// Whether a document is in one of a query's collections under `parent` and passes its filter
+fn query_matches(doc: &Document, parent: &str, query: &StructuredQuery) -> bool {
+    query.from.iter().any(|from| in_collection(&doc.name, parent, &from.collection_id, from.all_descendants))
+        && query.r#where.as_ref().map_or(Ok(true), |filter| matches(doc, filter)).unwrap_or(false)
+}
+
+/// Whether a document sits directly in (or, with `all_descendants`, anywhere below `parent` in) a collection with this id
+fn in_collection(name: &str, parent: &str, collection: &str, all_descendants: bool) -> bool {
+    let rest = match name.strip_prefix(parent).and_then(|rest| rest.strip_prefix('/')) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let segments: Vec<&str> = rest.split('/').collect();
+    let in_own_collection = segments.len() >= 2 && segments[segments.len() - 2] == collection;
+    in_own_collection && (all_descendants || segments.len() == 2)
+}
+
+/// Evaluate a query filter against a document
+/// An unsupported operator comes back as an error, which the caller treats as not matching
+fn matches(doc: &Document, filter: &Filter) -> Result<bool, ()> {
+    match filter.filter_type.as_ref().ok_or(())? {
+        filter::FilterType::CompositeFilter(composite) => {
+            let mut results = composite.filters.iter().map(|filter| matches(doc, filter));
+            match composite.op() {
+                composite_filter::Operator::And => results.try_fold(true, |all, result| Ok(all && result?)),
+                composite_filter::Operator::Or => results.try_fold(false, |any, result| Ok(any || result?)),
+                composite_filter::Operator::Unspecified => Err(()),
+            }
+        }
+        filter::FilterType::FieldFilter(field_filter) => {
+            let field = field_filter.field.as_ref().ok_or(())?;
+            let target = field_filter.value.as_ref().ok_or(())?;
+            let value = match field_value(doc, &field.field_path) {
+                Some(value) => value,
+                None => return Ok(false),
+            };
+            let same_type = type_rank(&value) == type_rank(target);
+            let ordering = cmp_values(&value, target);
+            let elements = |value: &Value| match &value.value_type {
+                Some(value::ValueType::ArrayValue(array)) => Some(array.values.clone()),
+                _ => None,
+            };
+            Ok(match field_filter.op() {
+                field_filter::Operator::Equal => ordering.is_eq(),
+                field_filter::Operator::NotEqual => !is_null(&value) && ordering.is_ne(),
+                field_filter::Operator::LessThan => same_type && ordering.is_lt(),
+                field_filter::Operator::LessThanOrEqual => same_type && ordering.is_le(),
+                field_filter::Operator::GreaterThan => same_type && ordering.is_gt(),
+                field_filter::Operator::GreaterThanOrEqual => same_type && ordering.is_ge(),
+                field_filter::Operator::In => elements(target).ok_or(())?.iter().any(|target| cmp_values(&value, target).is_eq()),
+                field_filter::Operator::NotIn => !is_null(&value) && !elements(target).ok_or(())?.iter().any(|target| cmp_values(&value, target).is_eq()),
+                field_filter::Operator::ArrayContains => elements(&value).is_some_and(|values| values.iter().any(|value| cmp_values(value, target).is_eq())),
+                field_filter::Operator::ArrayContainsAny => {
+                    let targets = elements(target).ok_or(())?;
+                    elements(&value).is_some_and(|values| values.iter().any(|value| targets.iter().any(|target| cmp_values(value, target).is_eq())))
+                }
+                field_filter::Operator::Unspecified => return Err(()),
+            })
+        }
+        filter::FilterType::UnaryFilter(unary) => {
+            let unary_filter::OperandType::Field(field) = unary.operand_type.as_ref().ok_or(())?;
+            let value = match field_value(doc, &field.field_path) {
+                Some(value) => value,
+                None => return Ok(false),
+            };
+            let is_nan = matches!(value.value_type, Some(value::ValueType::DoubleValue(double)) if double.is_nan());
+            Ok(match unary.op() {
+                unary_filter::Operator::IsNull => is_null(&value),
+                unary_filter::Operator::IsNotNull => !is_null(&value),
+                unary_filter::Operator::IsNan => is_nan,
+                unary_filter::Operator::IsNotNan => !is_nan && !is_null(&value),
+                unary_filter::Operator::Unspecified => return Err(()),
+            })
+        }
+    }
+}
+
+fn is_null(value: &Value) -> bool {
+    matches!(value.value_type, None | Some(value::ValueType::NullValue(_)))
+}
+
+/// Flip an ordering for descending sorts
+fn directed(ordering: Ordering, direction: Direction) -> Ordering {
+    match direction {
+        Direction::Descending => ordering.reverse(),
+        _ => ordering,
+    }
+}
+
+/// Compare a document's position in the query order against a cursor, which may name fewer values than there are orderings
+fn cmp_to_cursor(doc: &Document, order: &[(String, Direction)], cursor: &Cursor) -> Ordering {
+    order.iter()
+        .zip(&cursor.values)
+        .map(|((field, direction), value)| match field_value(doc, field) {
+            Some(field) => directed(cmp_values(&field, value), *direction),
+            None => Ordering::Less,
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// The value at a field path in a document, with `DOC_ID_FIELD` giving a reference to the document itself
+pub(crate) fn field_value(doc: &Document, path: &str) -> Option<Value> {
+    if path == DOC_ID_FIELD {
+        return Some(Value { value_type: Some(value::ValueType::ReferenceValue(doc.name.clone())) });
+    }
+    get_path(&doc.fields, &parse_path(path)).cloned()
+}
+
+/// A copy of a document holding only the selected field paths
+fn project<'a>(doc: Document, paths: impl Iterator<Item = &'a str>) -> Document {
+    let mut fields = HashMap::new();
+    for path in paths.filter(|path| *path != DOC_ID_FIELD).map(parse_path) {
+        if let Some(value) = get_path(&doc.fields, &path) {
+            set_path(&mut fields, &path, value.clone());
+        }
+    }
+    Document { fields, ..doc }
+}
+
+/// Split a field path like ``a.`b.c`.d`` into its segments
+fn parse_path(path: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut quoted = false;
+    for c in path.chars() {
+        match c {
+            '`' => quoted = !quoted,
+            '.' if !quoted => segments.push(String::new()),
+            _ => segments.last_mut().unwrap().push(c),
+        }
+    }
+    segments
+}
+
+fn get_path<'a>(fields: &'a HashMap<String, Value>, path: &[String]) -> Option<&'a Value> {
+    let value = fields.get(&path[0])?;
+    match (&value.value_type, path.len()) {
+        (_, 1) => Some(value),
+        (Some(value::ValueType::MapValue(map)), _) => get_path(&map.fields, &path[1..]),
+        _ => None,
+    }
+}
+
+fn set_path(fields: &mut HashMap<String, Value>, path: &[String], value: Value) {
+    if path.len() == 1 {
+        fields.insert(path[0].clone(), value);
+        return;
+    }
+    let entry = fields.entry(path[0].clone()).or_insert_with(|| Value { value_type: None });
+    if !matches!(entry.value_type, Some(value::ValueType::MapValue(_))) {
+        entry.value_type = Some(value::ValueType::MapValue(MapValue { fields: HashMap::new() }));
+    }
+    if let Some(value::ValueType::MapValue(map)) = &mut entry.value_type {
+        set_path(&mut map.fields, &path[1..], value);
+    }
+}
+
+fn remove_path(fields: &mut HashMap<String, Value>, path: &[String]) {
+    if path.len() == 1 {
+        fields.remove(&path[0]);
+    } else if let Some(Value { value_type: Some(value::ValueType::MapValue(map)) }) = fields.get_mut(&path[0]) {
+        remove_path(&mut map.fields, &path[1..]);
+    }
+}
+
+/// Where a value's type sorts relative to other types, following firestore's ordering
+/// Integers and doubles share a rank since firestore compares them as numbers
+fn type_rank(value: &Value) -> u8 {
+    match &value.value_type {
+        None | Some(value::ValueType::NullValue(_)) => 0,
+        Some(value::ValueType::BooleanValue(_)) => 1,
+        Some(value::ValueType::IntegerValue(_)) | Some(value::ValueType::DoubleValue(_)) => 2,
+        Some(value::ValueType::TimestampValue(_)) => 3,
+        Some(value::ValueType::StringValue(_)) => 4,
+        Some(value::ValueType::BytesValue(_)) => 5,
+        Some(value::ValueType::ReferenceValue(_)) => 6,
+        Some(value::ValueType::GeoPointValue(_)) => 7,
+        Some(value::ValueType::ArrayValue(_)) => 8,
+        Some(value::ValueType::MapValue(_)) => 9,
+    }
+}
+
+/// Compare two values the way firestore sorts them
+fn cmp_values(a: &Value, b: &Value) -> Ordering {
+    use value::ValueType::*;
+    match (&a.value_type, &b.value_type) {
+        (Some(BooleanValue(a)), Some(BooleanValue(b))) => a.cmp(b),
+        (Some(IntegerValue(a)), Some(IntegerValue(b))) => a.cmp(b),
+        (Some(IntegerValue(a)), Some(DoubleValue(b))) => cmp_doubles(*a as f64, *b),
+        (Some(DoubleValue(a)), Some(IntegerValue(b))) => cmp_doubles(*a, *b as f64),
+        (Some(DoubleValue(a)), Some(DoubleValue(b))) => cmp_doubles(*a, *b),
+        (Some(TimestampValue(a)), Some(TimestampValue(b))) => (a.seconds, a.nanos).cmp(&(b.seconds, b.nanos)),
+        (Some(StringValue(a)), Some(StringValue(b))) => a.cmp(b),
+        (Some(BytesValue(a)), Some(BytesValue(b))) => a.cmp(b),
+        (Some(ReferenceValue(a)), Some(ReferenceValue(b))) => a.split('/').cmp(b.split('/')),
+        (Some(GeoPointValue(a)), Some(GeoPointValue(b))) => {
+            cmp_doubles(a.latitude, b.latitude).then_with(|| cmp_doubles(a.longitude, b.longitude))
+        }
+        (Some(ArrayValue(a)), Some(ArrayValue(b))) => {
+            a.values.iter().zip(&b.values)
+                .map(|(a, b)| cmp_values(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.values.len().cmp(&b.values.len()))
+        }
+        (Some(MapValue(a)), Some(MapValue(b))) => {
+            let mut a: Vec<_> = a.fields.iter().collect();
+            let mut b: Vec<_> = b.fields.iter().collect();
+            a.sort_by(|x, y| x.0.cmp(y.0));
+            b.sort_by(|x, y| x.0.cmp(y.0));
+            a.iter().zip(&b)
+                .map(|((a_key, a_value), (b_key, b_value))| a_key.cmp(b_key).then_with(|| cmp_values(a_value, b_value)))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        }
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+/// Compare two numbers, with NaN sorting before every other number
+fn cmp_doubles(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+        _ => a.partial_cmp(&b).unwrap(),
+    }
+}
@@ -0,0 +1,28 @@
+use crate::CloudSyncError;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Receives the outcome of every `CloudSync` operation, e.g. to feed counts and latencies into a metrics system
+/// Register one with `set_observer()`; until then nothing is called
+pub trait Observer: Send + Sync {
+
+    /// Called once an operation has finished, with its name (like `"save"` or `"get"`), collection and how long it took
+    fn on_operation(&self, op: &'static str, collection: &str, duration: Duration, result: Result<(), &CloudSyncError>);
+}
+
+static OBSERVER: RwLock<Option<Arc<dyn Observer>>> = RwLock::new(None);
+
+/// Send the outcome of every operation from now on to `observer`, replacing any observer set before
+pub fn set_observer(observer: impl Observer + 'static) {
+    *OBSERVER.write().unwrap() = Some(Arc::new(observer));
+}
+
+/// Stop sending operations to the observer set with `set_observer()`
+pub fn clear_observer() {
+    *OBSERVER.write().unwrap() = None;
+}
+
+/// The observer operations should be reported to, if one is set
+pub(crate) fn observer() -> Option<Arc<dyn Observer>> {
+    OBSERVER.read().unwrap().clone()
+}
@@ -0,0 +1,158 @@
+use crate::retry::with_retry;
+use crate::{deserialize_docs, doc_ref, missing_index, query_params, query_value, range_field, trace, DOC_ID_FIELD};
+use crate::{Backend, CLConfig, CloudSync, CloudSyncError, Filter, FilterOp, Order, ToDocId, Unique};
+use chrono::{DateTime, Utc};
+use firestore::{FirestoreQueryCursor, FirestoreQueryFilter, FirestoreQueryFilterComposite, FirestoreQueryFilterCompositeOperator, FirestoreQueryOrder, FirestoreQueryParams, FirestoreValue};
+use gcloud_sdk::google::firestore::v1::{value, Value};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// A query over the collection objects of type `S` live in, built up fluently and run with `fetch()`
+/// Get one with `CloudSync::query()`, e.g. `User::query().filter("age", FilterOp::Gte, 18).order_by("name", Order::Asc).limit(20).fetch().await`
+///
+/// Filters are combined with AND. As with `get_where_all()`, range filters (and `NotEq` / `NotIn`) on more than one field fail
+/// with `InvalidQuery`, and a query needing a composite index that doesn't exist yet fails with `MissingIndex`.
+/// Without an explicit `order_by()`, a query with range filters is ordered by the field they're on, as firestore requires
+pub struct Query<S, T, B> {
+    cfg: CLConfig,
+    filters: Vec<Filter>,
+    order_by: Vec<(String, Order)>,
+    limit: Option<u32>,
+    start_after: Vec<CursorValue>,
+    error: Option<CloudSyncError>,
+    _objects: PhantomData<Marker<S, T, B>>,
+}
+
+/// Ties a query to its object, uuid and backend types without owning any of them
+type Marker<S, T, B> = fn() -> (S, T, B);
+
+impl<S, T, B> Query<S, T, B> where
+    S: CloudSync<T, B>,
+    for<'a> S: Deserialize<'a> + Serialize + Unique<T> + Sync + Send + 'static,
+    T: Serialize + ToDocId + std::cmp::Eq + std::hash::Hash + Send + Sync,
+    B: Backend {
+
+    /// A query over every object in the collection `cfg` names
+    pub(crate) fn new(cfg: CLConfig) -> Self {
+        Query { cfg, filters: vec![], order_by: vec![], limit: None, start_after: vec![], error: None, _objects: PhantomData }
+    }
+
+    /// Only match objects whose `field` compares to `value` with `op`
+    /// Nested fields can be reached with dot notation (e.g. `"address.city"`); a value that can't be serialized fails `fetch()`
+    pub fn filter(mut self, field: impl Into<String>, op: FilterOp, value: impl Serialize) -> Self {
+        match Filter::new(field, op, value) {
+            Ok(filter) => self.filters.push(filter),
+            Err(err) => self.error = self.error.or(Some(err)),
+        }
+        self
+    }
+
+    /// Only match objects meeting a condition built beforehand with `Filter::new()`
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Sort the results on `field` (or on the document id with `DOC_ID_FIELD`), after any fields already ordered by
+    pub fn order_by(mut self, field: impl Into<String>, direction: Order) -> Self {
+        self.order_by.push((field.into(), direction));
+        self
+    }
+
+    /// Return at most `limit` objects, instead of the config's `default_query_limit`
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Start just after the object holding this value in the field ordered by, e.g. the last value of the previous page
+    /// Call it once per `order_by()` field, in the same order. Without an `order_by()` results are ordered by document id,
+    /// so the value is the id to start after; ids are also accepted for an explicit `DOC_ID_FIELD` order
+    pub fn start_after(mut self, value: impl Serialize) -> Self {
+        let ordered = self.order_by.get(self.start_after.len()).map_or(DOC_ID_FIELD, |(field, _)| field.as_str());
+        let null = || FirestoreValue::from(Value { value_type: Some(value::ValueType::NullValue(0)) });
+        match query_value(&value) {
+            Ok(Some(value)) => match &value.value.value_type {
+                Some(value::ValueType::StringValue(id)) if ordered == DOC_ID_FIELD => self.start_after.push(CursorValue::Id(id.clone())),
+                _ => self.start_after.push(CursorValue::Value(value)),
+            },
+            Ok(None) => self.start_after.push(CursorValue::Value(null())),
+            Err(err) => self.error = self.error.or(Some(err)),
+        }
+        self
+    }
+
+    /// Read the collection as it was at `read_time` instead of as it is now (see `CLConfig::read_time`)
+    pub fn at_time(mut self, read_time: DateTime<Utc>) -> Self {
+        self.cfg.read_time = Some(read_time);
+        self
+    }
+
+    /// Run the query, returning every matching object
+    pub async fn fetch(self) -> Result<Vec<S>, CloudSyncError> {
+        self.run("query").await
+    }
+
+    /// Run the query traced as the operation `op`, for the `CloudSync` helpers built on it
+    pub(crate) async fn run(self, op: &'static str) -> Result<Vec<S>, CloudSyncError> {
+        let (collection, timeout) = (self.cfg.collection_id(), self.cfg.operation_timeout);
+        trace::traced(op, &collection, None, timeout, self.execute()).await
+    }
+
+    /// Run the query untraced, for the `CloudSync` helpers that run it as one step of a traced operation
+    pub(crate) async fn execute(mut self) -> Result<Vec<S>, CloudSyncError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let cfg = &self.cfg;
+        let backend = B::connect(cfg).await?;
+        let parent = cfg.parent(backend.documents_path());
+        let params = self.params(&parent)?;
+        let docs = with_retry!(cfg, backend.query(params.clone())).map_err(missing_index)?;
+        deserialize_docs(cfg, docs).await
+    }
+
+    /// The firestore query for the collection under `parent`
+    fn params(&self, parent: &str) -> Result<FirestoreQueryParams, CloudSyncError> {
+        let collection = self.cfg.collection_id();
+        let mut params = query_params(parent, &collection);
+        let range = range_field(&self.filters)?;
+        match self.filters.as_slice() {
+            [] => {}
+            [filter] => params = params.with_filter(filter.op.filter(&filter.field, filter.value.clone())),
+            filters => {
+                params = params.with_filter(FirestoreQueryFilter::Composite(FirestoreQueryFilterComposite {
+                    for_all_filters: filters.iter().map(|filter| filter.op.filter(&filter.field, filter.value.clone())).collect(),
+                    operator: FirestoreQueryFilterCompositeOperator::And,
+                }))
+            }
+        }
+        let mut order_by = self.order_by.clone();
+        if let (true, Some(field)) = (order_by.is_empty(), range) {
+            order_by.push((field.to_string(), Order::Asc));
+        }
+        if !order_by.is_empty() {
+            params = params.with_order_by(order_by.into_iter().map(|(field, direction)| FirestoreQueryOrder::new(field, direction.into())).collect());
+        }
+        if let Some(limit) = self.limit.or(self.cfg.default_query_limit) {
+            params = params.with_limit(limit);
+        }
+        if !self.start_after.is_empty() {
+            let cursor = self.start_after.iter()
+                .map(|value| match value {
+                    CursorValue::Id(id) => doc_ref(parent, &collection, id),
+                    CursorValue::Value(value) => Ok(value.clone()),
+                })
+                .collect::<Result<_, _>>()?;
+            params = params.with_start_at(FirestoreQueryCursor::AfterValue(cursor));
+        }
+        Ok(params)
+    }
+}
+
+/// One value of a `start_after()` cursor
+/// A document id only becomes the reference firestore compares `DOC_ID_FIELD` against once the collection's path is known
+enum CursorValue {
+    Id(String),
+    Value(FirestoreValue),
+}
@@ -0,0 +1,92 @@
+use crate::CloudSyncError;
+use firestore::errors::FirestoreError;
+use rand::Rng;
+use std::time::Duration;
+
+/// How transient firestore errors are retried
+///
+/// # Fields:
+/// - max_attempts: the most times an operation is tried, including the first try (1 disables retrying)
+/// - initial_backoff: the delay before the first retry, doubled after every retry
+/// - max_backoff: the cap on the delay between two attempts
+/// - total_timeout: no retry is started once this much time has passed since the first attempt
+///
+/// Each delay is randomly jittered between zero and the current backoff
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub total_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            total_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// The jittered delay to wait before retry number `attempt` (starting at 0)
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt)).min(self.max_backoff);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+/// Whether an error is worth retrying, e.g. firestore being briefly `UNAVAILABLE` or a `DEADLINE_EXCEEDED`
+pub(crate) fn is_transient(err: &CloudSyncError) -> bool {
+    match err {
+        CloudSyncError::Firestore(FirestoreError::DatabaseError(db_err)) => db_err.retry_possible || db_err.public.code == "DeadlineExceeded",
+        CloudSyncError::Connection(FirestoreError::NetworkError(_)) => true,
+        _ => false,
+    }
+}
+
+/// Await a firestore operation, retrying it according to the config's `RetryPolicy` while it fails with transient errors
+/// The operation expression is evaluated again for every attempt, and every attempt goes through the config's circuit breaker
+macro_rules! with_retry {
+    ($cfg:expr, $op:expr) => {{
+        let cfg: &$crate::CLConfig = $cfg;
+        let policy = &cfg.retry;
+        let started = std::time::Instant::now();
+        let mut attempt = 0;
+        loop {
+            let result = match $crate::circuit::acquire(cfg) {
+                Ok(permit) => {
+                    let result = $op.await.map_err($crate::CloudSyncError::from);
+                    $crate::circuit::record(cfg, permit, &result);
+                    result
+                }
+                Err(err) => Err(err),
+            };
+            match result {
+                Err(err) if attempt + 1 < policy.max_attempts && $crate::retry::is_transient(&err) => {
+                    let delay = policy.backoff(attempt);
+                    if started.elapsed() + delay > policy.total_timeout {
+                        break Err(err);
+                    }
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(attempt, error = %err, "retrying transient firestore error");
+                    $crate::runtime::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        }
+    }};
+}
+pub(crate) use with_retry;
@@ -0,0 +1,72 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Wait for `duration` on tokio's timer
+#[cfg(feature = "runtime-tokio")]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+/// Wait for `duration` without relying on any particular executor, by parking a helper thread until it has passed
+/// This is only used for retry backoffs and timeouts, so the extra thread per wait doesn't matter
+#[cfg(not(feature = "runtime-tokio"))]
+pub(crate) async fn sleep(duration: Duration) {
+    let (done, waited) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = done.send(());
+    });
+    let _ = waited.await;
+}
+
+/// Read a whole file without blocking the executor, on tokio's blocking pool
+#[cfg(feature = "runtime-tokio")]
+pub(crate) async fn read_to_string(path: PathBuf) -> std::io::Result<String> {
+    tokio::fs::read_to_string(path).await
+}
+
+/// Read a whole file without blocking the executor, on a helper thread
+#[cfg(not(feature = "runtime-tokio"))]
+pub(crate) async fn read_to_string(path: PathBuf) -> std::io::Result<String> {
+    let (done, read) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = done.send(std::fs::read_to_string(path));
+    });
+    read.await.unwrap_or_else(|_| Err(std::io::Error::other("file reading thread stopped")))
+}
+
+/// Run a piece of cpu-bound work on tokio's blocking pool, resuming its panic here if it panics
+#[cfg(feature = "runtime-tokio")]
+pub(crate) async fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(output) => output,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Run a piece of cpu-bound work on a helper thread
+#[cfg(not(feature = "runtime-tokio"))]
+pub(crate) async fn spawn_blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    let (done, finished) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = done.send(f());
+    });
+    finished.await.expect("blocking thread panicked")
+}
+
+/// Run `fut` to completion, or return `None` once `duration` has passed without it finishing
+#[cfg(feature = "runtime-tokio")]
+pub(crate) async fn timeout<F: Future>(duration: Duration, fut: F) -> Option<F::Output> {
+    tokio::time::timeout(duration, fut).await.ok()
+}
+
+/// Run `fut` to completion, or return `None` once `duration` has passed without it finishing
+#[cfg(not(feature = "runtime-tokio"))]
+pub(crate) async fn timeout<F: Future>(duration: Duration, fut: F) -> Option<F::Output> {
+    use futures::future::{select, Either};
+    match select(std::pin::pin!(fut), std::pin::pin!(sleep(duration))).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
@@ -0,0 +1,45 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// What a pending `ServerTimestamp` serializes to, which the write is built around before it's sent
+pub(crate) const SERVER_TIMESTAMP_SENTINEL: &str = "__cloudsync_server_timestamp__";
+
+/// A timestamp field firestore fills in with its own clock when the object is saved, so clients with skewed clocks
+/// still agree on times like `created_at`
+///
+/// # Variants:
+/// - Pending: not set yet; saving the object turns the field into a server timestamp transform instead of writing a value
+/// - At: the time read back from firestore, which is written as it is when the object is saved again
+///
+/// Read the object back (or use `save_and_read()`) to learn the time firestore assigned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ServerTimestamp {
+    #[default]
+    Pending,
+    At(DateTime<Utc>),
+}
+
+impl ServerTimestamp {
+    /// The assigned time, or `None` while it's still pending
+    pub fn time(&self) -> Option<DateTime<Utc>> {
+        match self {
+            ServerTimestamp::Pending => None,
+            ServerTimestamp::At(time) => Some(*time),
+        }
+    }
+}
+
+impl Serialize for ServerTimestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ServerTimestamp::Pending => serializer.serialize_str(SERVER_TIMESTAMP_SENTINEL),
+            ServerTimestamp::At(time) => firestore::serialize_as_timestamp::serialize(time, serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ServerTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        firestore::serialize_as_timestamp::deserialize(deserializer).map(ServerTimestamp::At)
+    }
+}
@@ -0,0 +1,100 @@
+use crate::retry::with_retry;
+use crate::{clear_docs, from_docs, query_params, set_write, Backend, CLConfig, CloudSyncError, FirestoreBackend, ToDocId, Unique};
+use crate::MAX_BATCH_WRITES;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::runtime::{Builder, Handle, RuntimeFlavor};
+
+/// How long cleaning up on drop may take when it has to run on its own thread
+const DROP_CLEAR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A collection owned by one test: it points at a uniquely named collection, and clears it once it's dropped
+/// so tests can't leak documents into each other or into later runs
+///
+/// Its helpers read and write the collection directly. To use an object's own `CloudSync` methods on it instead,
+/// have the object's `config()` name a collection from `TestCollection::unique_name()` (kept in a static) and guard that with `adopt()`
+///
+/// Clearing on drop blocks the test until it's done. Against firestore that needs a multi-threaded tokio runtime
+/// (`#[tokio::test(flavor = "multi_thread")]`); on any other runtime, call `teardown()` at the end of the test instead
+pub struct TestCollection<B: Backend = FirestoreBackend> {
+    cfg: CLConfig,
+    backend: B,
+    cleared: bool,
+}
+
+impl<B: Backend> TestCollection<B> {
+    /// Guard a new collection named after `cfg.collection` with a random suffix, connecting with the rest of `cfg`
+    pub async fn new(cfg: CLConfig) -> Result<Self, CloudSyncError> {
+        let collection = Self::unique_name(&cfg.collection);
+        Self::adopt(CLConfig { collection, ..cfg }).await
+    }
+
+    /// Guard the collection `cfg` already names, e.g. one made with `unique_name()`
+    /// A `collection_prefix` in `cfg` is folded into the guarded collection's name
+    pub async fn adopt(cfg: CLConfig) -> Result<Self, CloudSyncError> {
+        let cfg = CLConfig { collection: cfg.collection_id(), collection_prefix: None, ..cfg };
+        let backend = B::connect(&cfg).await?;
+        Ok(TestCollection { cfg, backend, cleared: false })
+    }
+
+    /// A collection name starting with `prefix` that no other test (or test run) will use
+    pub fn unique_name(prefix: &str) -> String {
+        format!("{}_test_{:016x}", prefix, rand::random::<u64>())
+    }
+
+    /// The name of the guarded collection
+    pub fn name(&self) -> &str {
+        &self.cfg.collection
+    }
+
+    /// The config pointing at the guarded collection
+    pub fn config(&self) -> &CLConfig {
+        &self.cfg
+    }
+
+    /// Save these objects to the collection under their document ids, in as few commits as possible
+    pub async fn seed<S, T>(&self, objs: &[S]) -> Result<(), CloudSyncError> where S: Serialize + Unique<T>, T: Serialize + ToDocId {
+        let parent = self.cfg.parent(self.backend.documents_path());
+        for chunk in objs.chunks(MAX_BATCH_WRITES) {
+            let writes = chunk.iter()
+                .map(|obj| set_write(&self.cfg, &parent, &self.cfg.collection, &obj.doc_id(), obj))
+                .collect::<Result<Vec<_>, _>>()?;
+            with_retry!(&self.cfg, self.backend.commit(writes.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Get every object in the collection
+    pub async fn get_all<S: for<'a> Deserialize<'a>>(&self) -> Result<Vec<S>, CloudSyncError> {
+        let parent = self.cfg.parent(self.backend.documents_path());
+        let docs = with_retry!(&self.cfg, self.backend.query(query_params(&parent, &self.cfg.collection)))?;
+        from_docs(&docs)
+    }
+
+    /// Clear the collection now, returning how many documents were removed, rather than when the guard is dropped
+    pub async fn teardown(mut self) -> Result<usize, CloudSyncError> {
+        self.cleared = true;
+        clear_docs(&self.backend, &self.cfg).await
+    }
+}
+
+impl<B: Backend> Drop for TestCollection<B> {
+    fn drop(&mut self) {
+        if self.cleared {
+            return;
+        }
+        let (cfg, backend) = (self.cfg.clone(), self.backend.clone());
+        // Cleaning up is best effort: a failure here shouldn't turn into a panic that hides the test's own result
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                let _ = tokio::task::block_in_place(|| handle.block_on(clear_docs(&backend, &cfg)));
+            }
+            _ => {
+                let _ = std::thread::spawn(move || {
+                    let runtime = Builder::new_current_thread().enable_all().build().ok()?;
+                    runtime.block_on(crate::runtime::timeout(DROP_CLEAR_TIMEOUT, clear_docs(&backend, &cfg)))
+                }).join();
+            }
+        }
+    }
+}
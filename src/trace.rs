@@ -0,0 +1,90 @@
+use crate::CloudSyncError;
+use firestore::FirestoreQueryParams;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Run one `CloudSync` operation inside a tracing span recording the operation, collection, document id and elapsed time
+/// Failures are also emitted as an error event, so a subscriber sees them without the caller logging anything
+#[cfg(feature = "tracing")]
+pub(crate) async fn traced<R>(
+    op: &'static str,
+    collection: &str,
+    id: Option<&str>,
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<R, CloudSyncError>>,
+) -> Result<R, CloudSyncError> {
+    use tracing::Instrument;
+    let span = tracing::info_span!("cloudsync", op, collection, id, elapsed_ms = tracing::field::Empty);
+    let started = Instant::now();
+    let result = timed(timeout, fut).instrument(span.clone()).await;
+    span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+    if let Err(err) = &result {
+        tracing::error!(parent: &span, error = %err, "cloudsync {} failed", op);
+    }
+    observe(op, collection, started, &result);
+    result
+}
+
+/// Without the `tracing` feature operations just run as they are
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn traced<R>(
+    op: &'static str,
+    collection: &str,
+    _id: Option<&str>,
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<R, CloudSyncError>>,
+) -> Result<R, CloudSyncError> {
+    let started = Instant::now();
+    let result = timed(timeout, fut).await;
+    observe(op, collection, started, &result);
+    result
+}
+
+/// Log a query at debug level right before a backend runs it, so the collection, filters, order and limit actually sent can be checked
+#[cfg(feature = "tracing")]
+pub(crate) fn log_query(params: &FirestoreQueryParams) {
+    tracing::debug!(
+        collection = ?params.collection_id,
+        parent = ?params.parent,
+        filter = ?params.filter,
+        order_by = ?params.order_by,
+        limit = ?params.limit,
+        offset = ?params.offset,
+        start_at = ?params.start_at,
+        end_at = ?params.end_at,
+        fields = ?params.return_only_fields,
+        "running query"
+    );
+}
+
+/// Without the `tracing` feature queries aren't logged
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn log_query(_params: &FirestoreQueryParams) {}
+
+/// Log a document a dry run would have deleted
+#[cfg(feature = "tracing")]
+pub(crate) fn log_dry_run_delete(name: &str) {
+    tracing::info!(document = name, "dry run, not deleting");
+}
+
+/// Without the `tracing` feature dry runs only count what they would delete
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn log_dry_run_delete(_name: &str) {}
+
+/// Report a finished operation to the registered `Observer`, if there is one
+fn observe<R>(op: &'static str, collection: &str, started: Instant, result: &Result<R, CloudSyncError>) {
+    if let Some(observer) = crate::observer::observer() {
+        observer.on_operation(op, collection, started.elapsed(), result.as_ref().map(|_| ()));
+    }
+}
+
+/// Give up on an operation with `Timeout` once it has run for longer than `timeout`, if there is one
+async fn timed<R>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<R, CloudSyncError>>,
+) -> Result<R, CloudSyncError> {
+    match timeout {
+        Some(timeout) => crate::runtime::timeout(timeout, fut).await.unwrap_or(Err(CloudSyncError::Timeout(timeout))),
+        None => fut.await,
+    }
+}